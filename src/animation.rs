@@ -0,0 +1,359 @@
+use ggez::graphics::Vector2;
+use ggez::{Context, GameError, GameResult};
+
+use std::collections::HashMap;
+use std::io::Read;
+
+use toml;
+
+use atlas::{AtlasBuilder, Region};
+use world::LetterKind;
+
+/// The file describing the sheets and clips.
+const PATH: &str = "/animations.toml";
+
+/// The clips the game plays by name, which the animations file has to contain.
+const REQUIRED_CLIPS: &[&str] = &[
+    "idle_down",
+    "idle_up",
+    "idle_left",
+    "idle_right",
+    "walk_down",
+    "walk_up",
+    "walk_left",
+    "walk_right",
+    "carry_down",
+    "carry_up",
+    "carry_left",
+    "carry_right",
+    "deliver",
+    "letter",
+    "letter_held",
+    "express",
+    "express_held",
+    "parcel",
+    "parcel_held",
+    "registered",
+    "registered_held",
+    "fragile",
+    "fragile_held",
+    "house_1",
+];
+
+/// A sprite sheet as described in the animations file.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SheetSpec {
+    image: String,
+    frame_width: u32,
+    frame_height: u32,
+}
+
+fn default_frame_time() -> f32 {
+    0.1
+}
+
+fn default_looping() -> bool {
+    true
+}
+
+/// A clip as described in the animations file.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ClipSpec {
+    sheet: String,
+    frames: Vec<usize>,
+    #[serde(default = "default_frame_time")]
+    frame_time: f32,
+    #[serde(default = "default_looping")]
+    looping: bool,
+    #[serde(default)]
+    mirror: bool,
+    #[serde(default)]
+    offsets: Vec<[f32; 2]>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AnimationsFile {
+    sheets: HashMap<String, SheetSpec>,
+    clips: HashMap<String, ClipSpec>,
+}
+
+/// An image in the atlas split into frames of the same size, numbered row by row from the top
+/// left.
+struct Sheet {
+    region: Region,
+    frame_width: u32,
+    frame_height: u32,
+}
+
+impl Sheet {
+    fn columns(&self) -> u32 {
+        self.region.w / self.frame_width
+    }
+
+    fn frame_count(&self) -> usize {
+        (self.columns() * (self.region.h / self.frame_height)) as usize
+    }
+
+    /// The part of the atlas with the frame.
+    fn frame_region(&self, frame: usize) -> Region {
+        let column = frame as u32 % self.columns();
+        let row = frame as u32 / self.columns();
+        self.region.part(
+            column * self.frame_width,
+            row * self.frame_height,
+            self.frame_width,
+            self.frame_height,
+        )
+    }
+}
+
+/// A single frame of a clip, as it should be drawn.
+#[derive(Clone, Copy, Debug)]
+pub struct Frame {
+    pub region: Region,
+    /// Whether the frame is drawn mirrored horizontally.
+    pub mirror: bool,
+    /// How far the frame is moved, in pixels of the sheet.
+    pub offset: Vector2,
+}
+
+/// A sequence of frames from a single sheet, each shown for the same time.
+pub struct Clip {
+    sheet: usize,
+    frames: Vec<usize>,
+    frame_time: f32,
+    looping: bool,
+    /// Whether the frames are drawn mirrored horizontally.
+    mirror: bool,
+    /// How far each frame is moved, in pixels of the sheet. Empty if the frames aren't moved.
+    offsets: Vec<Vector2>,
+}
+
+impl Clip {
+    /// The time it takes to play every frame once.
+    pub fn duration(&self) -> f32 {
+        self.frame_time * self.frames.len() as f32
+    }
+
+    /// Whether a clip that doesn't loop has shown its last frame for its full time.
+    pub fn finished(&self, time: f32) -> bool {
+        !self.looping && time >= self.duration()
+    }
+
+    /// The position in `frames` to show after `time` seconds. Clips that don't loop stop at
+    /// their last frame.
+    fn frame_index(&self, time: f32) -> usize {
+        let index = (time.max(0.0) / self.frame_time) as usize;
+        if self.looping {
+            index % self.frames.len()
+        } else {
+            index.min(self.frames.len() - 1)
+        }
+    }
+}
+
+/// Every sheet and clip from the animations file.
+pub struct Animations {
+    sheets: Vec<Sheet>,
+    clips: HashMap<String, Clip>,
+}
+
+impl Animations {
+    /// Load the animations file, and add the images of its sheets to the atlas.
+    pub fn load(ctx: &mut Context, atlas: &mut AtlasBuilder) -> GameResult<Animations> {
+        let mut source = String::new();
+        ctx.filesystem.open(PATH)?.read_to_string(&mut source)?;
+        let file: AnimationsFile = toml::from_str(&source)
+            .map_err(|e| GameError::ConfigError(format!("Invalid animations {}: {}", PATH, e)))?;
+        Animations::from_file(ctx, atlas, file).map_err(|message| {
+            GameError::ConfigError(format!("Invalid animations {}: {}", PATH, message))
+        })
+    }
+
+    fn from_file(
+        ctx: &mut Context,
+        atlas: &mut AtlasBuilder,
+        file: AnimationsFile,
+    ) -> Result<Animations, String> {
+        let mut sheets = Vec::new();
+        let mut sheet_indices = HashMap::new();
+        for (name, spec) in file.sheets {
+            let region = atlas.add(ctx, &spec.image).map_err(|e| {
+                format!(
+                    "could not load {} for sheet \"{}\": {}",
+                    spec.image, name, e
+                )
+            })?;
+            if spec.frame_width == 0
+                || spec.frame_height == 0
+                || region.w % spec.frame_width != 0
+                || region.h % spec.frame_height != 0
+            {
+                return Err(format!(
+                    "sheet \"{}\" is {}x{} pixels, which can't be split into {}x{} frames",
+                    name, region.w, region.h, spec.frame_width, spec.frame_height
+                ));
+            }
+            sheet_indices.insert(name, sheets.len());
+            sheets.push(Sheet {
+                region,
+                frame_width: spec.frame_width,
+                frame_height: spec.frame_height,
+            });
+        }
+
+        let mut clips = HashMap::new();
+        for (name, spec) in file.clips {
+            let sheet = match sheet_indices.get(&spec.sheet) {
+                Some(&sheet) => sheet,
+                None => {
+                    return Err(format!(
+                        "clip \"{}\" uses unknown sheet \"{}\"",
+                        name, spec.sheet
+                    ))
+                }
+            };
+            let frame_count = sheets[sheet].frame_count();
+            if spec.frames.is_empty() || spec.frames.iter().any(|&frame| frame >= frame_count) {
+                return Err(format!(
+                    "clip \"{}\" should have frames from 0 to {}",
+                    name,
+                    frame_count - 1
+                ));
+            }
+            if !(spec.frame_time > 0.0) {
+                return Err(format!(
+                    "clip \"{}\" should have a positive frame_time",
+                    name
+                ));
+            }
+            if !spec.offsets.is_empty() && spec.offsets.len() != spec.frames.len() {
+                return Err(format!(
+                    "clip \"{}\" has {} frames, but {} offsets",
+                    name,
+                    spec.frames.len(),
+                    spec.offsets.len()
+                ));
+            }
+            clips.insert(
+                name,
+                Clip {
+                    sheet,
+                    frames: spec.frames,
+                    frame_time: spec.frame_time,
+                    looping: spec.looping,
+                    mirror: spec.mirror,
+                    offsets: spec
+                        .offsets
+                        .iter()
+                        .map(|offset| Vector2::new(offset[0], offset[1]))
+                        .collect(),
+                },
+            );
+        }
+
+        for name in REQUIRED_CLIPS {
+            if !clips.contains_key(*name) {
+                return Err(format!("the clip \"{}\" is missing", name));
+            }
+        }
+        Ok(Animations { sheets, clips })
+    }
+
+    /// The clip with the given name. Every clip the game uses is checked to exist on loading.
+    pub fn clip(&self, name: &str) -> &Clip {
+        match self.clips.get(name) {
+            Some(clip) => clip,
+            None => panic!("There is no animation clip named \"{}\"", name),
+        }
+    }
+
+    /// The number of house sprites, which are the clips `house_1`, `house_2` and so on.
+    pub fn house_count(&self) -> usize {
+        (0..)
+            .take_while(|&i| self.clips.contains_key(&house_clip_name(i)))
+            .count()
+    }
+
+    /// The frame of the clip that shows after `time` seconds.
+    pub fn frame(&self, name: &str, time: f32) -> Frame {
+        let clip = self.clip(name);
+        let index = clip.frame_index(time);
+        Frame {
+            region: self.sheets[clip.sheet].frame_region(clip.frames[index]),
+            mirror: clip.mirror,
+            offset: clip
+                .offsets
+                .get(index)
+                .cloned()
+                .unwrap_or_else(|| Vector2::new(0.0, 0.0)),
+        }
+    }
+}
+
+/// The name of the clip for the house sprite with the given index, counting from 0.
+pub fn house_clip_name(index: usize) -> String {
+    format!("house_{}", index + 1)
+}
+
+/// The name of the clip for a kind of letter, either lying on the ground or in the bag.
+pub fn letter_clip_name(kind: LetterKind, held: bool) -> String {
+    let name = match kind {
+        LetterKind::Normal => "letter",
+        kind => kind.name(),
+    };
+    if held {
+        format!("{}_held", name)
+    } else {
+        name.to_string()
+    }
+}
+
+/// Plays a single clip at a time, keeping track of how long it has been playing.
+#[derive(Clone, Debug)]
+pub struct AnimationPlayer {
+    clip: String,
+    time: f32,
+}
+
+impl AnimationPlayer {
+    pub fn new(clip: &str) -> AnimationPlayer {
+        AnimationPlayer {
+            clip: clip.to_string(),
+            time: 0.0,
+        }
+    }
+
+    pub fn clip(&self) -> &str {
+        &self.clip
+    }
+
+    /// Switch to the clip, starting it from the beginning unless it is already playing.
+    pub fn play(&mut self, clip: &str) {
+        if self.clip != clip {
+            self.restart(clip);
+        }
+    }
+
+    /// Start the clip from the beginning, even when it is already playing.
+    pub fn restart(&mut self, clip: &str) {
+        self.clip = clip.to_string();
+        self.time = 0.0;
+    }
+
+    pub fn update(&mut self, delta: f32) {
+        self.time += delta;
+    }
+
+    pub fn finished(&self, animations: &Animations) -> bool {
+        animations.clip(&self.clip).finished(self.time)
+    }
+
+    /// The frame to show now.
+    pub fn frame(&self, animations: &Animations) -> Frame {
+        animations.frame(&self.clip, self.time)
+    }
+}
@@ -0,0 +1,146 @@
+use ggez::graphics::{FilterMode, Image, Rect};
+use ggez::{Context, GameError, GameResult};
+
+use std::collections::HashMap;
+use std::io::Read;
+
+use image;
+
+/// The width of the atlas in pixels. The images are packed on shelves of this width, and the
+/// atlas grows downwards as shelves are added.
+const ATLAS_WIDTH: u32 = 1024;
+
+/// The transparent gap left around every image, so neighbouring images don't bleed into each
+/// other when drawn at fractional positions.
+const PADDING: u32 = 1;
+
+/// A rectangle of the atlas, in pixels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
+}
+
+impl Region {
+    /// A part of this region, relative to its top left.
+    pub fn part(&self, x: u32, y: u32, w: u32, h: u32) -> Region {
+        Region {
+            x: self.x + x,
+            y: self.y + y,
+            w,
+            h,
+        }
+    }
+}
+
+/// Packs images into an atlas as they are added, so the region of an image is known right
+/// away.
+pub struct AtlasBuilder {
+    /// The RGBA pixels of the atlas so far, `ATLAS_WIDTH` pixels per row.
+    pixels: Vec<u8>,
+    height: u32,
+    /// The left of the next image on the current shelf.
+    cursor: u32,
+    shelf_top: u32,
+    shelf_height: u32,
+    regions: HashMap<String, Region>,
+}
+
+impl AtlasBuilder {
+    pub fn new() -> AtlasBuilder {
+        AtlasBuilder {
+            pixels: Vec::new(),
+            height: 0,
+            cursor: 0,
+            shelf_top: 0,
+            shelf_height: 0,
+            regions: HashMap::new(),
+        }
+    }
+
+    /// Add the image at `path` to the atlas, or find where it already is.
+    pub fn add(&mut self, ctx: &mut Context, path: &str) -> GameResult<Region> {
+        if let Some(&region) = self.regions.get(path) {
+            return Ok(region);
+        }
+        let mut bytes = Vec::new();
+        ctx.filesystem.open(path)?.read_to_end(&mut bytes)?;
+        let image = image::load_from_memory(&bytes)
+            .map_err(|e| GameError::ResourceLoadError(format!("Could not load {}: {}", path, e)))?
+            .to_rgba();
+        let (w, h) = image.dimensions();
+        if w > ATLAS_WIDTH {
+            return Err(GameError::ResourceLoadError(format!(
+                "{} is {} pixels wide, but the atlas is only {} wide",
+                path, w, ATLAS_WIDTH
+            )));
+        }
+
+        // Start a new shelf when the image doesn't fit on the current one
+        if self.cursor + w > ATLAS_WIDTH {
+            self.shelf_top += self.shelf_height + PADDING;
+            self.shelf_height = 0;
+            self.cursor = 0;
+        }
+        let region = Region {
+            x: self.cursor,
+            y: self.shelf_top,
+            w,
+            h,
+        };
+        self.cursor += w + PADDING;
+        self.shelf_height = self.shelf_height.max(h);
+        if self.shelf_top + self.shelf_height > self.height {
+            self.height = self.shelf_top + self.shelf_height;
+            self.pixels
+                .resize((ATLAS_WIDTH * self.height * 4) as usize, 0);
+        }
+
+        let source = image.into_raw();
+        for y in 0..h {
+            let from = (y * w * 4) as usize;
+            let to = (((region.y + y) * ATLAS_WIDTH + region.x) * 4) as usize;
+            self.pixels[to..to + (w * 4) as usize]
+                .copy_from_slice(&source[from..from + (w * 4) as usize]);
+        }
+        self.regions.insert(path.to_string(), region);
+        Ok(region)
+    }
+
+    /// Upload the atlas to the GPU.
+    pub fn build(self, ctx: &mut Context) -> GameResult<Atlas> {
+        let mut image =
+            Image::from_rgba8(ctx, ATLAS_WIDTH as u16, self.height as u16, &self.pixels)?;
+        image.set_filter(FilterMode::Nearest);
+        Ok(Atlas {
+            image,
+            width: ATLAS_WIDTH,
+            height: self.height,
+        })
+    }
+}
+
+/// Every sprite of the game in a single image, so they can all be drawn from sprite batches
+/// without switching textures.
+pub struct Atlas {
+    pub image: Image,
+    width: u32,
+    height: u32,
+}
+
+impl Atlas {
+    /// The source rectangle in the atlas image for `src`, which is a fraction of the region in
+    /// the same way the source rectangle of a `DrawParam` is a fraction of an image.
+    pub fn src(&self, region: Region, src: Rect) -> Rect {
+        let width = self.width as f32;
+        let height = self.height as f32;
+        Rect::new(
+            (region.x as f32 + src.x * region.w as f32) / width,
+            (region.y as f32 + src.y * region.h as f32) / height,
+            src.w * region.w as f32 / width,
+            src.h * region.h as f32 / height,
+        )
+    }
+}
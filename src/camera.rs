@@ -0,0 +1,170 @@
+use ggez::graphics::{DrawParam, Point2, Vector2};
+
+use layout::{Layout, REFERENCE_WIDTH};
+
+/// How far, in tiles, the target can move from the centre of the view before the camera starts
+/// following it.
+const DEAD_ZONE: [f32; 2] = [1.0, 0.75];
+
+/// How quickly the camera catches up with the target and the zoom level, as the fraction of the
+/// remaining distance covered per second on a logarithmic scale.
+const FOLLOW_RATE: f32 = 6.0;
+
+/// How far the view may show past the edges of the level, in tiles, so the trees around it
+/// can be seen.
+const VIEW_MARGIN: f32 = 1.0;
+
+const MIN_ZOOM: f32 = 0.5;
+const MAX_ZOOM: f32 = 2.0;
+/// The factor every step of zooming in or out multiplies the zoom with.
+const ZOOM_STEP: f32 = 1.25;
+
+/// How far the view moves at most while shaking, in tiles.
+const MAX_SHAKE: f32 = 0.3;
+/// How much of the shake wears off per second.
+const SHAKE_DECAY: f32 = 1.5;
+
+/// The size of a tile in the sprites, and in the level layers before they are scaled to the
+/// screen.
+pub const TILE_PIXELS: f32 = 32.0;
+
+/// Which part of the level is shown on the screen, and how large.
+///
+/// The camera follows a target with some delay, stays inside the level, and can zoom and shake.
+/// Positions in the level are in tiles, positions on the screen in pixels.
+#[derive(Clone, Debug)]
+pub struct Camera {
+    /// The centre of the view in tiles, before shaking.
+    centre: Point2,
+    /// The number of tiles on the width of the reference screen at a zoom of 1.
+    tiles_on_width: f32,
+    zoom: f32,
+    target_zoom: f32,
+    /// How strongly the view is shaking, from 0 to 1.
+    shake: f32,
+    /// The time the camera has been running, to vary the shake.
+    time: f32,
+    layout: Layout,
+    level_size: Vector2,
+}
+
+impl Camera {
+    /// A camera showing `target` in the centre, as far as the level allows.
+    pub fn new(tiles_on_width: f32, layout: Layout, level_size: Vector2, target: Point2) -> Camera {
+        let mut camera = Camera {
+            centre: target,
+            tiles_on_width,
+            zoom: 1.0,
+            target_zoom: 1.0,
+            shake: 0.0,
+            time: 0.0,
+            layout,
+            level_size,
+        };
+        camera.clamp();
+        camera
+    }
+
+    /// Show the level on a screen that may have been resized.
+    pub fn set_layout(&mut self, layout: Layout) {
+        self.layout = layout;
+        self.clamp();
+    }
+
+    /// Move towards `target` if it left the dead zone, and advance the zoom and shake.
+    pub fn update(&mut self, target: Point2, delta: f32) {
+        let mut desired = self.centre;
+        for axis in 0..2 {
+            let distance = target[axis] - self.centre[axis];
+            if distance > DEAD_ZONE[axis] {
+                desired[axis] = target[axis] - DEAD_ZONE[axis];
+            } else if distance < -DEAD_ZONE[axis] {
+                desired[axis] = target[axis] + DEAD_ZONE[axis];
+            }
+        }
+        let follow = 1.0 - (-FOLLOW_RATE * delta).exp();
+        self.centre += (desired - self.centre) * follow;
+        self.zoom += (self.target_zoom - self.zoom) * follow;
+        self.shake = (self.shake - SHAKE_DECAY * delta).max(0.0);
+        self.time += delta;
+        self.clamp();
+    }
+
+    pub fn zoom_in(&mut self) {
+        self.target_zoom = (self.target_zoom * ZOOM_STEP).min(MAX_ZOOM);
+    }
+
+    pub fn zoom_out(&mut self) {
+        self.target_zoom = (self.target_zoom / ZOOM_STEP).max(MIN_ZOOM);
+    }
+
+    /// Start shaking the view, adding to any shaking that is going on. `strength` goes from 0
+    /// to 1.
+    pub fn shake(&mut self, strength: f32) {
+        self.shake = (self.shake + strength).min(1.0);
+    }
+
+    /// The size of a tile on the screen, in pixels. Tiles scale with the interface, so a
+    /// larger screen shows more of the level only if its shape is different.
+    pub fn tile_size(&self) -> f32 {
+        REFERENCE_WIDTH / self.tiles_on_width * self.layout.scale() * self.zoom
+    }
+
+    /// The size of the view in tiles.
+    fn view_size(&self) -> Vector2 {
+        self.layout.size() / self.tile_size()
+    }
+
+    /// Keep the view inside the level and its margin. A level smaller than the view is centred.
+    fn clamp(&mut self) {
+        let view_size = self.view_size();
+        for axis in 0..2 {
+            let min = view_size[axis] / 2.0 - VIEW_MARGIN;
+            let max = self.level_size[axis] - view_size[axis] / 2.0 + VIEW_MARGIN;
+            self.centre[axis] = if min > max {
+                self.level_size[axis] / 2.0
+            } else {
+                self.centre[axis].max(min).min(max)
+            };
+        }
+    }
+
+    /// The position in tiles at the top left of the screen, including the shake.
+    fn top_left(&self) -> Point2 {
+        // The square makes small shakes subtle and large ones violent
+        let amount = self.shake * self.shake * MAX_SHAKE;
+        let shake = Vector2::new(
+            (self.time * 37.0).sin() * amount,
+            (self.time * 29.0).cos() * amount,
+        );
+        self.centre - self.view_size() / 2.0 + shake
+    }
+
+    pub fn world_to_screen(&self, point: Point2) -> Point2 {
+        Point2::origin() + (point - self.top_left()) * self.tile_size()
+    }
+
+    pub fn screen_to_world(&self, point: Point2) -> Point2 {
+        self.top_left() + point.coords / self.tile_size()
+    }
+
+    /// Whether any part of the square of `half_size` tiles around `point` is on the screen.
+    pub fn is_visible(&self, point: Point2, half_size: f32) -> bool {
+        let top_left = self.screen_to_world(Point2::origin());
+        let bottom_right = self.screen_to_world(Point2::origin() + self.layout.size());
+        (0..2).all(|axis| {
+            point[axis] + half_size > top_left[axis] && point[axis] - half_size < bottom_right[axis]
+        })
+    }
+
+    /// The parameters to draw something laid out at 32 pixels per tile from the level origin,
+    /// such as the level layers.
+    pub fn level_param(&self) -> DrawParam {
+        let scale = self.tile_size() / TILE_PIXELS;
+        DrawParam {
+            dest: self.world_to_screen(Point2::origin()),
+            scale: Point2::new(scale, scale),
+            ..Default::default()
+        }
+    }
+}
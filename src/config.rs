@@ -0,0 +1,376 @@
+use ggez::{Context, GameError, GameResult};
+
+use std::io::Read;
+
+use toml;
+
+use town;
+use world::LetterKind;
+
+/// How levels are generated when no level file is given.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Generator {
+    /// Houses scattered randomly over an open field.
+    Scatter,
+    /// A town with streets, blocks and numbered houses along the main street.
+    Town,
+}
+
+/// How a kind of letter is spawned and scored.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LetterRules {
+    /// How often the kind spawns, compared to the weights of the other kinds.
+    pub weight: u32,
+    /// What delivering a letter of the kind is worth.
+    pub score: u32,
+}
+
+/// The rules for every kind of letter.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LetterKinds {
+    pub normal: LetterRules,
+    pub express: LetterRules,
+    pub parcel: LetterRules,
+    pub registered: LetterRules,
+    pub fragile: LetterRules,
+}
+
+impl Default for LetterKinds {
+    fn default() -> LetterKinds {
+        let rules = |weight, score| LetterRules { weight, score };
+        LetterKinds {
+            normal: rules(10, 1),
+            express: rules(2, 3),
+            parcel: rules(2, 2),
+            registered: rules(2, 2),
+            fragile: rules(2, 2),
+        }
+    }
+}
+
+impl LetterKinds {
+    pub fn get(&self, kind: LetterKind) -> &LetterRules {
+        match kind {
+            LetterKind::Normal => &self.normal,
+            LetterKind::Express => &self.express,
+            LetterKind::Parcel => &self.parcel,
+            LetterKind::Registered => &self.registered,
+            LetterKind::Fragile => &self.fragile,
+        }
+    }
+
+    pub fn total_weight(&self) -> u32 {
+        LetterKind::ALL
+            .iter()
+            .map(|&kind| self.get(kind).weight)
+            .sum()
+    }
+}
+
+/// The tuning values of the game, loaded from `resources/config.toml`. Any value missing from
+/// the file keeps its default.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub generator: Generator,
+    /// The width of randomly generated levels in tiles.
+    pub level_width: u32,
+    /// The height of randomly generated levels in tiles.
+    pub level_height: u32,
+    /// How many tiles fit on the width of an 800 by 600 window. Other windows show as many tiles
+    /// in the direction they grew the least.
+    pub tiles_on_width: f32,
+    /// The time between the first letters spawning.
+    pub spawn_time: f32,
+    /// The factor the spawn time is multiplied with every time a letter spawns.
+    pub spawn_acceleration: f32,
+    pub house_count: u32,
+    /// The number of places in the bag. A letter takes one place and a parcel two, and the last
+    /// letter drops out when they don't fit.
+    pub max_holding: usize,
+    /// Whether the bag keeps the letters in order of their house numbers, instead of putting
+    /// new letters at the front and letting the player reorder them.
+    pub sorted_bag: bool,
+    /// The top speed of the player with an empty bag, in tiles per second.
+    pub player_speed: f32,
+    /// How quickly the player speeds up and changes direction, in tiles per second squared.
+    pub player_acceleration: f32,
+    /// How quickly the player slows down without any input, in tiles per second squared.
+    pub player_friction: f32,
+    /// The fraction of the top speed lost for every place taken up in the bag.
+    pub letter_slowdown: f32,
+    /// The game is over when this many places on the ground are taken by letters.
+    pub max_letters_on_ground: u32,
+    /// The seconds after spawning a letter is due.
+    pub due_time: f32,
+    /// The seconds a letter can be overdue before it takes two places on the ground.
+    pub overdue_time: f32,
+    /// The most score added for delivering a letter before it is due, earned when it is
+    /// delivered right away and shrinking towards the due time.
+    pub on_time_bonus: u32,
+    /// The most score added for delivering a letter quickly, earned when it is delivered right
+    /// away and shrinking to nothing at `speed_time`.
+    pub speed_bonus: u32,
+    /// The seconds after spawning a letter earns a speed bonus.
+    pub speed_time: f32,
+    /// The extra multiplier for every letter after the first that is handed over at a house at
+    /// once.
+    pub multiple_delivery_bonus: f32,
+    /// The seconds after a delivery within which the next one continues the combo.
+    pub combo_time: f32,
+    /// The score added to a delivery for every delivery before it in the combo.
+    pub combo_bonus: u32,
+    /// The seconds after spawning an express letter is worth its score. After that it is worth
+    /// as much as a normal letter.
+    pub express_time: f32,
+    /// The seconds the player has to stand at the house to hand over a registered letter.
+    pub registered_time: f32,
+    /// The score lost when a fragile letter falls out of a full bag.
+    pub fragile_penalty: u32,
+    pub letters: LetterKinds,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            generator: Generator::Town,
+            level_width: 30,
+            level_height: 20,
+            tiles_on_width: 15.0,
+            spawn_time: 30.0,
+            spawn_acceleration: 0.95,
+            house_count: 6,
+            max_holding: 4,
+            sorted_bag: false,
+            player_speed: 7.0,
+            player_acceleration: 40.0,
+            player_friction: 30.0,
+            letter_slowdown: 0.08,
+            max_letters_on_ground: 10,
+            due_time: 60.0,
+            overdue_time: 30.0,
+            on_time_bonus: 3,
+            speed_bonus: 5,
+            speed_time: 20.0,
+            multiple_delivery_bonus: 0.5,
+            combo_time: 10.0,
+            combo_bonus: 1,
+            express_time: 30.0,
+            registered_time: 1.5,
+            fragile_penalty: 2,
+            letters: LetterKinds::default(),
+        }
+    }
+}
+
+impl Config {
+    pub fn load(ctx: &mut Context, path: &str) -> GameResult<Config> {
+        let mut source = String::new();
+        ctx.filesystem.open(path)?.read_to_string(&mut source)?;
+        Config::parse(&source).map_err(|e| match e {
+            GameError::ConfigError(message) => {
+                GameError::ConfigError(format!("Invalid config {}: {}", path, message))
+            }
+            e => e,
+        })
+    }
+
+    pub fn parse(source: &str) -> GameResult<Config> {
+        let config: Config =
+            toml::from_str(source).map_err(|e| GameError::ConfigError(e.to_string()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// A hash of every value, so replays can tell whether they are played back with the config
+    /// they were recorded with. It only depends on the values, so it is the same on every run.
+    pub fn hash(&self) -> u64 {
+        // FNV-1a
+        format!("{:?}", self)
+            .bytes()
+            .fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+                (hash ^ byte as u64).wrapping_mul(0x100_0000_01b3)
+            })
+    }
+
+    /// The maximum number of houses that are sure to fit on the level. Scattered houses are
+    /// placed randomly and at least four tiles apart, so this is half of what fits when packed
+    /// tightly. Towns fit as many houses as there are plots along the main street.
+    pub fn max_house_count(&self) -> u32 {
+        match self.generator {
+            Generator::Scatter => {
+                let columns = (self.level_width.saturating_sub(6)) / 4 + 1;
+                let rows = (self.level_height.saturating_sub(6)) / 4 + 1;
+                columns * rows / 2
+            }
+            Generator::Town => town::max_house_count(self.level_width),
+        }
+    }
+
+    fn validate(&self) -> GameResult<()> {
+        let error = |message: String| Err(GameError::ConfigError(message));
+        if self.level_width < 12 || self.level_height < 8 {
+            return error(format!(
+                "the level is {}x{} tiles, but should be at least 12x8",
+                self.level_width, self.level_height
+            ));
+        }
+        if !(self.tiles_on_width > 0.0) || self.tiles_on_width > self.level_width as f32 {
+            return error(format!(
+                "tiles_on_width is {}, but should be between 0 and the level width ({})",
+                self.tiles_on_width, self.level_width
+            ));
+        }
+        if !(self.spawn_time > 0.0) {
+            return error(format!(
+                "spawn_time is {}, but should be positive",
+                self.spawn_time
+            ));
+        }
+        if !(self.spawn_acceleration > 0.0 && self.spawn_acceleration <= 1.0) {
+            return error(format!(
+                "spawn_acceleration is {}, but should be above 0 and at most 1",
+                self.spawn_acceleration
+            ));
+        }
+        if self.house_count == 0 || self.house_count > self.max_house_count() {
+            return error(format!(
+                "there are {} houses, but between 1 and {} fit on a {}x{} level",
+                self.house_count,
+                self.max_house_count(),
+                self.level_width,
+                self.level_height
+            ));
+        }
+        if self.max_holding == 0 {
+            return error("max_holding should be at least 1".to_string());
+        }
+        if !(self.player_speed > 0.0) {
+            return error(format!(
+                "player_speed is {}, but should be positive",
+                self.player_speed
+            ));
+        }
+        if !(self.player_acceleration > 0.0) {
+            return error(format!(
+                "player_acceleration is {}, but should be positive",
+                self.player_acceleration
+            ));
+        }
+        if !(self.player_friction > 0.0) {
+            return error(format!(
+                "player_friction is {}, but should be positive",
+                self.player_friction
+            ));
+        }
+        let full_bag_slowdown = self.letter_slowdown * self.max_holding as f32;
+        if !(self.letter_slowdown >= 0.0 && full_bag_slowdown < 1.0) {
+            return error(format!(
+                "letter_slowdown is {}, but should be at least 0 and below 1 / max_holding",
+                self.letter_slowdown
+            ));
+        }
+        if self.max_letters_on_ground == 0 {
+            return error("max_letters_on_ground should be at least 1".to_string());
+        }
+        if !(self.due_time > 0.0) {
+            return error(format!(
+                "due_time is {}, but should be positive",
+                self.due_time
+            ));
+        }
+        if !(self.overdue_time >= 0.0) {
+            return error(format!(
+                "overdue_time is {}, but should be at least 0",
+                self.overdue_time
+            ));
+        }
+        if !(self.speed_time > 0.0) {
+            return error(format!(
+                "speed_time is {}, but should be positive",
+                self.speed_time
+            ));
+        }
+        if !(self.multiple_delivery_bonus >= 0.0) {
+            return error(format!(
+                "multiple_delivery_bonus is {}, but should be at least 0",
+                self.multiple_delivery_bonus
+            ));
+        }
+        if !(self.combo_time >= 0.0) {
+            return error(format!(
+                "combo_time is {}, but should be at least 0",
+                self.combo_time
+            ));
+        }
+        if !(self.express_time > 0.0) {
+            return error(format!(
+                "express_time is {}, but should be positive",
+                self.express_time
+            ));
+        }
+        if !(self.registered_time >= 0.0) {
+            return error(format!(
+                "registered_time is {}, but should be at least 0",
+                self.registered_time
+            ));
+        }
+        if self.letters.total_weight() == 0 {
+            return error("at least one kind of letter should have a weight".to_string());
+        }
+        if self.letters.parcel.weight > 0 && self.max_holding < LetterKind::Parcel.size() {
+            return error(format!(
+                "parcels take {} places in the bag, but max_holding is {}",
+                LetterKind::Parcel.size(),
+                self.max_holding
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parse `source`, which should be rejected, and return why.
+    fn rejection(source: &str) -> String {
+        match Config::parse(source) {
+            Err(GameError::ConfigError(message)) => message,
+            Err(e) => panic!("{:?} failed with {:?}", source, e),
+            Ok(_) => panic!("{:?} was accepted", source),
+        }
+    }
+
+    #[test]
+    fn bundled_config_loads() {
+        let config = Config::parse(include_str!("../resources/config.toml")).unwrap();
+        assert_eq!(config.hash(), Config::default().hash());
+        assert_eq!(Config::parse("").unwrap().hash(), config.hash());
+    }
+
+    #[test]
+    fn invalid_configs_are_rejected() {
+        assert_eq!(
+            rejection("level_width = 8"),
+            "the level is 8x20 tiles, but should be at least 12x8"
+        );
+        assert_eq!(
+            rejection("combo_time = -1.0"),
+            "combo_time is -1, but should be at least 0"
+        );
+        assert_eq!(
+            rejection("speed_time = 0.0"),
+            "speed_time is 0, but should be positive"
+        );
+        assert_eq!(
+            rejection("max_holding = 0"),
+            "max_holding should be at least 1"
+        );
+        assert!(rejection("speed = 1.0").contains("unknown field `speed`"));
+        assert!(rejection("due_time = \"soon\"").contains("invalid type"));
+    }
+}
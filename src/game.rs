@@ -1,819 +1,728 @@
-use ggez::event::{EventHandler, Keycode, Mod};
-use ggez::graphics::{DrawParam, Drawable, FilterMode, Point2, Rect, Text, Vector2};
+use ggez::graphics::spritebatch::SpriteBatch;
+use ggez::graphics::{DrawParam, Point2, Rect, Vector2};
 use ggez::*;
 use std::collections::VecDeque;
 
+use animation::{self, AnimationPlayer};
+use camera::{Camera, TILE_PIXELS};
+use game_over::GameOver;
+use game_state::{InputEvent, Shared, State, Transition};
+use hud::Hud;
 use images::Images;
+use menu::PauseMenu;
+use minimap::Minimap;
+use input::Action;
+use layout::Layout;
+use replay::{Playback, Replay};
+use scoring::Delivery;
+use config::Config;
+use level::{Level, Obstacle};
+use tilemap::{Terrain, TileMap};
+use world::{random_seed, BagAction, Events, House, Input, Letter, World, TICK_TIME};
 
 use std::f32::consts::PI;
 
-use rand::{thread_rng, Rng};
-
-const LEVEL_WIDTH: u32 = 30;
-const LEVEL_HEIGHT: u32 = 20;
+/// The most time simulated in a single frame. After a longer stall the game slows down instead
+/// of running many ticks at once.
+const MAX_FRAME_TIME: f32 = 0.25;
+
+/// The distance between the trees around the edge of the level, in tiles.
+const BORDER_TREE_SPACING: f32 = 1.6;
+
+/// How strongly the camera shakes when the postman drops a letter, from 0 to 1.
+const DROP_SHAKE: f32 = 0.6;
+
+/// The ticks between saves of the replay of a game that is still going, so even a game that
+/// crashes leaves a recent replay behind.
+const REPLAY_SAVE_TICKS: u64 = 10 * 60;
+
+/// The seconds a delivered letter takes to fly up into the house.
+const LETTER_FLIGHT_TIME: f32 = 0.2;
+/// The seconds the points of a delivery float above the postman.
+const SCORE_POPUP_TIME: f32 = 1.0;
+/// The size of the score popup digits, growing by `SCORE_POPUP_COMBO_GROWTH` for every
+/// delivery in the combo up to `SCORE_POPUP_MAX_COMBO`.
+const SCORE_POPUP_SCALE: f32 = 1.5;
+const SCORE_POPUP_COMBO_GROWTH: f32 = 0.25;
+const SCORE_POPUP_MAX_COMBO: u32 = 5;
+/// The distance between the lines of a score popup, in digit pixels.
+const SCORE_POPUP_LINE_HEIGHT: f32 = 9.0;
+
+/// The layers the level is drawn in, from back to front. Each layer is a single sprite batch
+/// drawing from the atlas, and the HUD is drawn over all of them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Layer {
+    /// The tiles of the map.
+    Terrain,
+    /// Houses with their signs, obstacles and the letters on the ground.
+    Objects,
+    /// The postman and the letters flying into the houses.
+    Actors,
+    /// The trees around the edge of the level.
+    Border,
+}
 
-const TILES_ON_WIDTH: f32 = 15.0;
+impl Layer {
+    const ALL: [Layer; 4] = [Layer::Terrain, Layer::Objects, Layer::Actors, Layer::Border];
 
-const SPAWN_TIME: f32 = 30.0;
+    /// Whether the layer is filled once when a game starts, rather than every frame.
+    fn is_static(self) -> bool {
+        match self {
+            Layer::Terrain | Layer::Border => true,
+            _ => false,
+        }
+    }
+}
 
-const HOUSE_COUNT: u32 = 6;
+/// A delivery that is being shown: the letter flying up into the house, and the points it
+/// earned floating up after it.
+struct LetterAnimation {
+    /// The seconds since the delivery.
+    time: f32,
+    delivery: Delivery,
+}
 
-const MAX_HOLDING: usize = 4;
+/// A position in the level, in the pixels of the level layers.
+fn level_pixels(position: Point2) -> Point2 {
+    position * TILE_PIXELS
+}
 
-const PLAYER_SPEED: f32 = 7.0;
-const MAX_LETTERS_ON_GROUND: u32 = 10;
+/// The side the postman faces for a heading in radians clockwise from up. Diagonals towards the
+/// top show the back, the others the side.
+fn facing(heading: f32) -> &'static str {
+    if heading.abs() < 3.0 * PI / 8.0 {
+        "up"
+    } else if heading.abs() > 7.0 * PI / 8.0 {
+        "down"
+    } else if heading > 0.0 {
+        "right"
+    } else {
+        "left"
+    }
+}
 
+/// Everything needed to start a new game.
 #[derive(Clone, Debug)]
-struct House {
-    position: Point2,
-    number: u32,
-    text: Text,
-    resource_type: usize,
+pub struct GameOptions {
+    pub config: Config,
+    /// The level to play, or `None` to generate a random one.
+    pub level: Option<Level>,
+    /// The file the level was loaded from, which is recorded in replays.
+    pub level_path: Option<String>,
+    /// The seed given on the command line, which is reused when restarting.
+    pub seed: Option<u64>,
 }
 
 impl House {
-    fn new(
-        ctx: &mut Context,
-        position: Point2,
-        number: u32,
-        image_count: usize,
-    ) -> GameResult<House> {
-        let mut text = Text::new(
-            ctx,
-            &format!("{}", number),
-            &graphics::Font::default_font()?,
-        )?;
-        text.set_filter(FilterMode::Nearest);
-        Ok(House {
-            position,
-            number,
-            text,
-            resource_type: thread_rng().gen_range(0, image_count),
-        })
-    }
-
-    fn draw(&self, ctx: &mut Context, game: &Game, offset: Vector2) -> GameResult<()> {
-        let scale = Game::tile_size(ctx) / 32.0;
-        let dest = Game::float_coord_to_screen(ctx, self.position - game.player.coords);
-        graphics::draw_ex(
-            ctx,
-            &game.images.houses[self.resource_type],
+    fn add_sprites(&self, batch: &mut SpriteBatch, images: &Images, time: f32) {
+        let dest = level_pixels(self.position);
+        images.add_clip(
+            batch,
+            &animation::house_clip_name(self.resource_type),
+            time,
             DrawParam {
-                dest: dest + offset,
-                rotation: 0.0,
+                dest,
                 offset: Point2::new(0.5, 0.5),
-                shear: Point2::new(0.0, 0.0),
-                scale: scale * 2.0,
+                scale: Point2::new(2.0, 2.0),
                 ..Default::default()
             },
-        )?;
-
-        graphics::draw_ex(
-            ctx,
-            &game.images.sign,
+        );
+        images.add(
+            batch,
+            images.sign,
             DrawParam {
-                dest: dest + offset,
-                rotation: 0.0,
+                dest,
                 offset: Point2::new(0.0, -0.2),
-                shear: Point2::new(0.0, 0.0),
-                scale,
                 ..Default::default()
             },
-        )?;
-        let number_dest = Game::float_coord_to_screen(
-            ctx,
-            self.position - game.player.coords + Vector2::new(1.0, 1.3),
         );
-        self.text.draw_ex(
-            ctx,
+        images.add_number(
+            batch,
+            self.number,
             DrawParam {
-                dest: number_dest + offset,
-                rotation: 0.0,
-                offset: Point2::new(0.5, 0.5),
-                shear: Point2::new(0.0, 0.0),
-                scale,
+                dest: level_pixels(self.position + Vector2::new(1.0, 1.3)),
+                scale: Point2::new(2.0, 2.0),
                 ..Default::default()
             },
-        )?;
-        Ok(())
-    }
-
-    fn intersects(&self, other: &House) -> bool {
-        let diff = self.position - other.position;
-        diff[0].abs() < 4.0 && diff[1].abs() < 4.0
-    }
-
-    fn player_intersection(&self, ctx: &mut Context, game: &Game) -> bool {
-        let screen_coords = graphics::get_screen_coordinates(ctx);
-        let w = screen_coords.w;
-        let h = screen_coords.h;
-        let player_pos = Point2::new(w / 2.0, h / 2.0);
-
-        let dest = Game::float_coord_to_screen(ctx, self.position - game.player.coords);
-
-        let dist = dest - player_pos;
-
-        (dist[0]).abs() < 64.0 && (dist[1]).abs() < 64.0
+        );
     }
 }
 
-#[derive(Clone, Debug)]
-struct Letter {
-    number: u32,
-    text: Text,
-    position: Point2,
-    dropped_time: f64,
+impl Obstacle {
+    fn add_sprites(&self, batch: &mut SpriteBatch, images: &Images) {
+        // The trees image is two tiles wide, so stretch it over the obstacle
+        images.add(
+            batch,
+            images.trees,
+            DrawParam {
+                dest: level_pixels(Point2::new(self.position[0], self.position[1])),
+                scale: Point2::new(self.size[0] / 2.0, self.size[1] / 2.0),
+                ..Default::default()
+            },
+        );
+    }
 }
 
 impl Letter {
-    fn new(ctx: &mut Context, position: Point2, number: u32) -> GameResult<Letter> {
-        let text = Text::new(
-            ctx,
-            &format!("{}", number),
-            &graphics::Font::default_font()?,
-        )?;
-        Ok(Letter {
-            number,
-            text,
-            position,
-            dropped_time: 0.0,
-        })
-    }
-
-    fn player_intersection(&self, ctx: &mut Context, game: &Game) -> bool {
-        let screen_coords = graphics::get_screen_coordinates(ctx);
-        let w = screen_coords.w;
-        let h = screen_coords.h;
-        let player_pos = Point2::new(w / 2.0, h / 2.0);
-
-        let dest = Game::float_coord_to_screen(ctx, self.position - game.player.coords);
-
-        let dist = dest - player_pos;
-
-        (dist[0]).abs() < 48.0
-            && (dist[1]).abs() < 48.0
-            && timer::duration_to_f64(timer::get_time_since_start(ctx)) > self.dropped_time
-    }
-
-    fn draw(&self, ctx: &mut Context, game: &Game, offset: Vector2) -> GameResult<()> {
-        let scale = Game::tile_size(ctx) / 32.0;
-        let dest = Game::float_coord_to_screen(ctx, self.position - game.player.coords);
-
-        let time = timer::duration_to_f64(timer::get_time_since_start(ctx));
-
-        game.images.letter.draw_ex(
-            ctx,
+    fn add_sprites(&self, batch: &mut SpriteBatch, images: &Images, time: f64) {
+        let dest = level_pixels(self.position);
+        images.add_clip(
+            batch,
+            &animation::letter_clip_name(self.kind, false),
+            time as f32,
             DrawParam {
-                dest: dest + offset,
-                rotation: 0.0,
+                dest,
                 offset: Point2::new(0.5, 0.5),
-                shear: Point2::new(0.0, 0.0),
-                scale: scale * 1.5,
-                color: if self.dropped_time > time {
+                scale: Point2::new(1.5, 1.5),
+                color: if self.on_cooldown(time) {
                     Some(graphics::Color::new(0.7, 0.7, 0.7, 1.0))
+                } else if self.overdue(time) {
+                    Some(graphics::Color::new(1.0, 0.4, 0.4, 1.0))
                 } else {
                     None
                 },
                 ..Default::default()
             },
-        )?;
-        self.text.draw_ex(
-            ctx,
-            DrawParam {
-                dest: dest + offset,
-                rotation: 0.0,
-                offset: Point2::new(0.5, 0.5),
-                shear: Point2::new(0.0, 0.0),
-                scale,
-                color: Some(graphics::Color::new(0.0, 0.0, 0.0, 1.0)),
-                ..Default::default()
-            },
-        )?;
-        Ok(())
-    }
-
-    fn draw_in_hand(&self, ctx: &mut Context, game: &Game, i: usize) -> GameResult<()> {
-        let scale = Game::tile_size(ctx) / 32.0;
-        let dest = Game::float_coord_to_screen(
-            ctx,
-            Point2::new(scale[0] * 0.5, scale[0] * 0.5 + scale[1] * 0.6 * i as f32),
         );
-
-        game.images.letter.draw_ex(
-            ctx,
-            DrawParam {
-                dest,
-                rotation: 0.0,
-                offset: Point2::new(0.5, 0.5),
-                shear: Point2::new(0.0, 0.0),
-                scale: scale * 1.5,
-                ..Default::default()
-            },
-        )?;
-        self.text.draw_ex(
-            ctx,
+        images.add_number(
+            batch,
+            self.number,
             DrawParam {
                 dest,
-                rotation: 0.0,
-                offset: Point2::new(0.5, 0.5),
-                shear: Point2::new(0.0, 0.0),
-                scale,
+                scale: Point2::new(1.5, 1.5),
                 color: Some(graphics::Color::new(0.0, 0.0, 0.0, 1.0)),
                 ..Default::default()
             },
-        )?;
-        Ok(())
+        );
     }
 }
 
-enum Direction {
-    Up,
-    UpRight,
-    Right,
-    DownRight,
-    Down,
-    DownLeft,
-    Left,
-    UpLeft,
-}
-
 pub struct Game<'a> {
-    player: Point2,
+    world: World,
     images: &'a Images,
 
-    right_pressed: bool,
-    left_pressed: bool,
-    up_pressed: bool,
-    down_pressed: bool,
-
-    player_direction: Direction,
-    player_running: bool,
-
-    houses: Vec<House>,
-    letters: Vec<Letter>,
-    holding_letters: VecDeque<Letter>,
-
-    time_since_last_letter: f32,
-
-    score: u32,
-    score_text: Text,
-
-    game_over: bool,
-
-    letter_animation: VecDeque<f32>,
+    options: GameOptions,
+
+    /// Real time that has passed but hasn't been simulated yet.
+    time_accumulator: f32,
+    ticks: u64,
+    /// The player position before the last tick, to interpolate between ticks when drawing.
+    previous_player: Point2,
+    recording: Replay,
+    playback: Option<Playback>,
+    /// How the result of the replay that was played back compares to the recorded one, to show
+    /// when the game is over.
+    replay_check: Option<String>,
+    /// What the player asked to do with the bag since the last tick.
+    bag_action: Option<BagAction>,
+
+    camera: Camera,
+    /// A sprite batch for every layer, in the order of `Layer::ALL`.
+    layers: Vec<SpriteBatch>,
+    hud: Hud,
+    minimap: Minimap,
+
+    letter_animation: VecDeque<LetterAnimation>,
+    postman: AnimationPlayer,
 
     music: audio::Source,
 
     sound_pickup: audio::Source,
     sound_drop: audio::Source,
     sound_drop_bad: audio::Source,
-    letter_spawn_time: f32,
-
-    game_over_text: Text
 }
 
 impl<'a> Game<'a> {
-    pub fn new(ctx: &mut Context, images: &'a Images) -> GameResult<Game<'a>> {
-        let houses = Game::generate_houses(ctx, images.houses.len())?;
+    pub fn new(
+        ctx: &mut Context,
+        images: &'a Images,
+        options: GameOptions,
+        playback: Option<Playback>,
+    ) -> GameResult<Game<'a>> {
+        let seed = match playback {
+            Some(ref playback) => {
+                let level_path = options.level_path.as_ref().map(String::as_str);
+                playback.replay().check_setup(level_path, &options.config)?;
+                playback.replay().seed
+            }
+            None => options.seed.unwrap_or_else(random_seed),
+        };
+
+        let world = World::new(
+            options.config.clone(),
+            options.level.as_ref(),
+            images.house_count(),
+            seed,
+        );
+        let previous_player = world.player;
 
-        let mut game_over_text = Text::new(
-            ctx,
-            &"Game over - press spacebar to restart",
-            &graphics::Font::default_font()?,
-        )?;
-        game_over_text.set_filter(FilterMode::Nearest);
+        let camera = Game::camera(ctx, &world);
+        let layers = Game::layers(images, &world);
+        let hud = Hud::new(ctx, images, &world, &camera)?;
+        let minimap = Minimap::new(ctx, images, &world)?;
+        let recording = Replay::new(seed, options.level_path.clone(), &options.config);
 
         Ok(Game {
-            player: Point2::new(10.0, 10.0),
+            world,
             images,
+            options,
 
-            right_pressed: false,
-            left_pressed: false,
-            up_pressed: false,
-            down_pressed: false,
-
-            player_direction: Direction::Down,
-            player_running: false,
-            houses,
-            letters: Vec::new(),
-            holding_letters: VecDeque::new(),
+            time_accumulator: 0.0,
+            ticks: 0,
+            previous_player,
+            recording,
+            playback,
+            replay_check: None,
+            bag_action: None,
 
-            time_since_last_letter: 0.0,
-
-            score: 0,
-            score_text: Game::get_score_text(0, ctx)?,
-
-            game_over: false,
+            camera,
+            layers,
+            hud,
+            minimap,
 
             letter_animation: VecDeque::new(),
+            postman: AnimationPlayer::new("idle_down"),
 
             music: audio::Source::new(ctx, "/music.wav")?,
 
             sound_pickup: audio::Source::new(ctx, "/pickup.wav")?,
             sound_drop: audio::Source::new(ctx, "/drop.wav")?,
             sound_drop_bad: audio::Source::new(ctx, "/drop_bad.wav")?,
-            letter_spawn_time: SPAWN_TIME,
-            game_over_text
         })
     }
 
-    fn generate_houses(ctx: &mut Context, resource_count: usize) -> GameResult<Vec<House>> {
-        let mut houses = Vec::new();
-        for house_number in 0..HOUSE_COUNT {
-            loop {
-                let position = Point2::new(
-                    thread_rng().gen_range(3.0, LEVEL_WIDTH as f32 - 3.0),
-                    thread_rng().gen_range(3.0, LEVEL_HEIGHT as f32 - 3.0),
-                );
-                let new_house = House::new(ctx, position, 1 + house_number, resource_count)?;
-                if houses
-                    .iter()
-                    .all(|house: &House| !house.intersects(&new_house))
-                {
-                    houses.push(new_house);
-                    break;
-                }
-            }
-        }
-        Ok(houses)
-    }
-
     fn restart(&mut self, ctx: &mut Context) -> GameResult<()> {
-        self.player = Point2::new(10.0, 10.0);
-        self.houses = Game::generate_houses(ctx, self.images.houses.len())?;
-        self.letters = Vec::new();
-        self.holding_letters = VecDeque::new();
-        self.time_since_last_letter = 0.0;
-        self.score = 0;
-        self.score_text = Game::get_score_text(0, ctx)?;
-        self.game_over = false;
+        self.save_unfinished(ctx)?;
+        let seed = self.options.seed.unwrap_or_else(random_seed);
+        self.world = World::new(
+            self.options.config.clone(),
+            self.options.level.as_ref(),
+            self.images.house_count(),
+            seed,
+        );
+        self.camera = Game::camera(ctx, &self.world);
+        self.layers = Game::layers(self.images, &self.world);
+        self.time_accumulator = 0.0;
+        self.ticks = 0;
+        self.previous_player = self.world.player;
+        self.recording = Replay::new(seed, self.options.level_path.clone(), &self.options.config);
+        self.playback = None;
+        self.replay_check = None;
+        self.bag_action = None;
+        self.hud = Hud::new(ctx, self.images, &self.world, &self.camera)?;
+        self.minimap = Minimap::new(ctx, self.images, &self.world)?;
         self.letter_animation = VecDeque::new();
-        self.letter_spawn_time = SPAWN_TIME;
+        self.postman = AnimationPlayer::new("idle_down");
         Ok(())
     }
 
-    fn get_score_text(score: u32, ctx: &mut Context) -> GameResult<Text> {
-        let mut text = Text::new(
-            ctx,
-            &format!("Score: {}", score),
-            &graphics::Font::default_font()?,
-        )?;
-
-        text.set_filter(FilterMode::Nearest);
-        Ok(text)
-    }
-
-    fn float_coord_to_screen(ctx: &Context, point: Point2) -> Point2 {
-        let width = graphics::get_screen_coordinates(ctx).w;
-        Point2::new(
-            point[0] / TILES_ON_WIDTH * width,
-            point[1] / TILES_ON_WIDTH * width,
-        )
-    }
-
-    fn screen_coord_to_float(ctx: &Context, point: Point2) -> Point2 {
-        let width = graphics::get_screen_coordinates(ctx).w;
-        Point2::new(
-            point[0] * TILES_ON_WIDTH / width,
-            point[1] * TILES_ON_WIDTH / width,
-        )
-    }
-
-    fn tile_size(ctx: &Context) -> Point2 {
-        let width = graphics::get_screen_coordinates(ctx).w;
-        Point2::new(width / TILES_ON_WIDTH, width / TILES_ON_WIDTH)
+    /// Save the replay of the game that just ended, and check it against the replay that was
+    /// played back, if any.
+    fn finish_game(&mut self, ctx: &mut Context) -> GameResult<()> {
+        let result = (self.ticks, self.world.score());
+        self.recording.result = Some(result);
+        self.save_recording(ctx)?;
+
+        self.replay_check = self
+            .playback
+            .as_ref()
+            .map(|playback| playback.replay().describe_result(result));
+        Ok(())
     }
 
-    fn calculate_player_direction(&mut self) {
-        self.player_running = true;
-        if self.left_pressed && !self.right_pressed {
-            // Left
-            if self.up_pressed && !self.down_pressed {
-                self.player_direction = Direction::UpLeft;
-            } else if self.down_pressed && !self.up_pressed {
-                self.player_direction = Direction::DownLeft;
-            } else {
-                self.player_direction = Direction::Left;
-            }
-        } else if self.right_pressed && !self.left_pressed {
-            // Right
-            if self.up_pressed && !self.down_pressed {
-                self.player_direction = Direction::UpRight;
-            } else if self.down_pressed && !self.up_pressed {
-                self.player_direction = Direction::DownRight;
-            } else {
-                self.player_direction = Direction::Right;
-            }
-        } else {
-            if self.up_pressed && !self.down_pressed {
-                self.player_direction = Direction::Up;
-            } else if self.down_pressed && !self.up_pressed {
-                self.player_direction = Direction::Down;
-            } else {
-                self.player_running = false;
-            }
-        }
+    /// Write the replay to `/last_replay.txt`, where it can be played back from.
+    fn save_recording(&self, ctx: &mut Context) -> GameResult<()> {
+        let file = ctx.filesystem.create("/last_replay.txt")?;
+        self.recording.write(file)
     }
 
-    fn draw_trees(&mut self, ctx: &mut Context, offset: Vector2) -> GameResult<()> {
-        let scale = Game::tile_size(ctx) / 32.0;
-        for i in 0..LEVEL_HEIGHT - 8 {
-            let pos = Point2::new(-0.8 - self.player[0], scale[0] * i as f32 - self.player[1]);
-            let dest = Game::float_coord_to_screen(ctx, pos);
-            self.images.trees.draw_ex(
-                ctx,
-                DrawParam {
-                    dest: dest + offset,
-                    rotation: 0.0,
-                    offset: Point2::new(0.5, 0.5),
-                    shear: Point2::new(0.0, 0.0),
-                    scale,
-                    ..Default::default()
-                },
-            )?;
-        }
-        for i in 0..LEVEL_HEIGHT - 8 {
-            let pos = Point2::new(
-                LEVEL_WIDTH as f32 - 0.2 - self.player[0],
-                scale[0] * i as f32 - self.player[1],
-            );
-            let dest = Game::float_coord_to_screen(ctx, pos);
-            self.images.trees.draw_ex(
-                ctx,
-                DrawParam {
-                    dest: dest + offset,
-                    rotation: PI,
-                    offset: Point2::new(0.5, 0.5),
-                    shear: Point2::new(0.0, 0.0),
-                    scale,
-                    ..Default::default()
-                },
-            )?;
-        }
-        for i in 0..LEVEL_WIDTH - 12 {
-            let pos = Point2::new(scale[0] * i as f32 - self.player[0], -0.8 - self.player[1]);
-            let dest = Game::float_coord_to_screen(ctx, pos);
-            self.images.trees.draw_ex(
-                ctx,
-                DrawParam {
-                    dest: dest + offset,
-                    rotation: 0.5 * PI,
-                    offset: Point2::new(0.5, 0.5),
-                    shear: Point2::new(0.0, 0.0),
-                    scale,
-                    ..Default::default()
-                },
-            )?;
-        }
-        for i in 0..LEVEL_WIDTH - 11 {
-            let pos = Point2::new(
-                scale[0] * i as f32 - self.player[0],
-                LEVEL_HEIGHT as f32 - 0.2 - self.player[1],
-            );
-            let dest = Game::float_coord_to_screen(ctx, pos);
-            self.images.trees.draw_ex(
-                ctx,
-                DrawParam {
-                    dest: dest + offset,
-                    rotation: 1.5 * PI,
-                    offset: Point2::new(0.5, 0.5),
-                    shear: Point2::new(0.0, 0.0),
-                    scale,
-                    ..Default::default()
-                },
-            )?;
+    /// Save the replay of a game that hasn't ended yet. Replays that are played back are left
+    /// alone, as they may have been read from the same file.
+    fn save_unfinished(&self, ctx: &mut Context) -> GameResult<()> {
+        if self.playback.is_none() && !self.world.game_over {
+            self.save_recording(ctx)?;
         }
         Ok(())
     }
-}
-
-impl<'a> EventHandler for Game<'a> {
-    fn update(&mut self, ctx: &mut Context) -> GameResult<()> {
-        if !self.music.playing() {
-            self.music.repeat();
-            self.music.play()?;
-        }
-
-        if self.game_over {
-
-        } else {
-            let delta = timer::duration_to_f64(timer::get_delta(ctx));
-            let d = delta as f32 * PLAYER_SPEED;
-            if self.player_running {
-                self.player += match self.player_direction {
-                    Direction::Up => Vector2::new(0.0, -d),
-                    Direction::Down => Vector2::new(0.0, d),
-                    Direction::Left => Vector2::new(-d, 0.0),
-                    Direction::Right => Vector2::new(d, 0.0),
-                    Direction::UpLeft => Vector2::new(-0.70 * d, -0.70 * d),
-                    Direction::DownLeft => Vector2::new(-0.70 * d, 0.70 * d),
-                    Direction::UpRight => Vector2::new(0.70 * d, -0.70 * d),
-                    Direction::DownRight => Vector2::new(0.70 * d, 0.70 * d),
-                };
-
-                let screen_coords = graphics::get_screen_coordinates(ctx);
-                let w = screen_coords.w;
-                let h = screen_coords.h;
-                let player_pos = Point2::new(w / 2.0, h / 2.0);
-                let diff = Game::screen_coord_to_float(ctx, player_pos);
-                if self.player[0] + diff[0] < 0.0 {
-                    self.player[0] = -diff[0];
-                } else if self.player[0] + diff[0] > LEVEL_WIDTH as f32 - 1.0 {
-                    self.player[0] = LEVEL_WIDTH as f32 - diff[0] - 1.0;
-                }
 
-                if self.player[1] + diff[1] < 0.0 {
-                    self.player[1] = -diff[1];
-                } else if self.player[1] + diff[1] > LEVEL_HEIGHT as f32 - 1.0 {
-                    self.player[1] = LEVEL_HEIGHT as f32 - diff[1] - 1.0;
+    /// Advance the game by a single tick of `TICK_TIME`, with the input from the controls
+    /// unless a replay is played back.
+    fn tick(&mut self, ctx: &mut Context, input: Input, sounds: bool) -> GameResult<()> {
+        let input = match self.playback {
+            Some(ref mut playback) => {
+                if !playback.has_tick(self.ticks) {
+                    // The recording ended before the game did
+                    return Ok(());
                 }
+                playback.input_for_tick(self.ticks)
             }
+            None => input,
+        };
+        self.recording.record_tick(input);
+        self.ticks += 1;
 
-            self.time_since_last_letter += d;
-            while self.time_since_last_letter > self.letter_spawn_time {
-                self.time_since_last_letter -= self.letter_spawn_time;
-                self.letter_spawn_time *= 0.95;
-                let position = Point2::new(
-                    thread_rng().gen_range(2.0, LEVEL_WIDTH as f32 - 2.0),
-                    thread_rng().gen_range(2.0, LEVEL_HEIGHT as f32 - 2.0),
-                );
-                let number = thread_rng().gen_range(1, HOUSE_COUNT + 1);
-                self.letters.push(Letter::new(ctx, position, number)?);
-            }
+        self.previous_player = self.world.player;
+        let events = self.world.update(TICK_TIME, &input);
 
-            // Pick up letters
-            let mut not_picked_up = Vec::new();
-            let mut any_picked_up = false;
-            let mut any_dropped = false;
-            while self.letters.len() > 0 {
-                let letter = self.letters.pop().unwrap();
-                if letter.player_intersection(ctx, &self) {
-                    self.holding_letters.push_front(letter);
-                    any_picked_up = true;
-                    if self.holding_letters.len() > MAX_HOLDING {
-                        let mut dropped_letter = self.holding_letters.pop_back().unwrap();
-                        dropped_letter.dropped_time =
-                            timer::duration_to_f64(timer::get_time_since_start(ctx)) + 2.0;
-                        let screen_coords = graphics::get_screen_coordinates(ctx);
-                        let w = screen_coords.w;
-                        let h = screen_coords.h;
-                        let player_pos = Vector2::new(w / 2.0, h / 2.0);
-                        dropped_letter.position = Game::screen_coord_to_float(
-                            ctx,
-                            Game::float_coord_to_screen(ctx, self.player) + player_pos,
-                        );
-                        not_picked_up.push(dropped_letter);
-                        any_dropped = true;
-                    }
-                } else {
-                    not_picked_up.push(letter);
-                }
-            }
-            self.letters = not_picked_up;
-            if any_dropped {
+        if sounds {
+            if events.dropped || events.broke {
                 self.sound_drop_bad.play()?;
-            } else if any_picked_up {
+            } else if events.picked_up || events.put_down {
                 self.sound_pickup.play()?;
             }
+        }
 
-            // Drop off letters
-            let mut updated_score = false;
-            for house in &self.houses {
-                if house.player_intersection(ctx, &self) {
-                    while self.holding_letters.len() > 0
-                        && self.holding_letters[0].number == house.number
-                    {
-                        self.holding_letters.pop_front();
-                        self.score += 1;
-                        updated_score = true;
-                    }
-                }
-            }
-            if updated_score {
-                self.score_text = Game::get_score_text(self.score, ctx)?;
-                self.letter_animation.push_front(0.0);
+        if events.dropped || events.broke {
+            self.camera.shake(DROP_SHAKE);
+        }
+        if let Some(delivery) = events.delivery {
+            self.letter_animation.push_front(LetterAnimation {
+                time: 0.0,
+                delivery,
+            });
+            if sounds {
                 self.sound_drop.play()?;
             }
+        }
 
-            if self.letters.len() >= MAX_LETTERS_ON_GROUND as usize {
-                self.game_over = true;
-            }
-
-            for animated_letter in &mut self.letter_animation {
-                *animated_letter += 5.0 * delta as f32;
-            }
-            while self.letter_animation.back().is_some()
-                && *self.letter_animation.back().unwrap() > 1.0
-            {
-                self.letter_animation.pop_back();
-            }
+        for animation in &mut self.letter_animation {
+            animation.time += TICK_TIME;
         }
+        while self.letter_animation.back().is_some()
+            && self.letter_animation.back().unwrap().time > SCORE_POPUP_TIME
+        {
+            self.letter_animation.pop_back();
+        }
+        self.animate_postman(&events);
 
+        if self.world.game_over {
+            self.finish_game(ctx)?;
+        } else if self.ticks % REPLAY_SAVE_TICKS == 0 {
+            self.save_unfinished(ctx)?;
+        }
         Ok(())
     }
 
-    fn draw(&mut self, ctx: &mut Context) -> GameResult<()> {
-        graphics::clear(ctx);
-
-        let screen_coords = graphics::get_screen_coordinates(ctx);
-        let w = screen_coords.w;
-        let h = screen_coords.h;
+    /// Pick the clip for what the postman is doing, and advance it by a tick. Delivering plays
+    /// to the end before walking takes over again.
+    fn animate_postman(&mut self, events: &Events) {
+        if events.delivery.is_some() {
+            self.postman.restart("deliver");
+        } else if self.postman.clip() != "deliver"
+            || self.postman.finished(&self.images.animations)
+        {
+            let action = if !self.world.player_running {
+                "idle"
+            } else if self.world.holding_letters.is_empty() {
+                "walk"
+            } else {
+                "carry"
+            };
+            let clip = format!("{}_{}", action, facing(self.world.player_heading));
+            self.postman.play(&clip);
+        }
+        self.postman.update(TICK_TIME);
+    }
 
-        let tiles_on_height = TILES_ON_WIDTH / w * h;
+    /// A sprite batch for every layer, with the static layers filled for the world.
+    fn layers(images: &Images, world: &World) -> Vec<SpriteBatch> {
+        Layer::ALL
+            .iter()
+            .map(|&layer| {
+                let mut batch = images.batch();
+                match layer {
+                    Layer::Terrain => Game::add_terrain(&mut batch, images, &world.tiles),
+                    Layer::Border => Game::add_border(&mut batch, images, world),
+                    _ => {}
+                }
+                batch
+            })
+            .collect()
+    }
 
-        let offset_x = if self.player[0] < -1.0 {
-            self.player[0] + 1.0
-        } else if self.player[0] > LEVEL_WIDTH as f32 - TILES_ON_WIDTH {
-            self.player[0] - (LEVEL_WIDTH as f32 - TILES_ON_WIDTH)
-        } else {
-            0.0
-        };
+    /// Add every tile of the map, grouped by terrain.
+    fn add_terrain(batch: &mut SpriteBatch, images: &Images, tiles: &TileMap) {
+        for &terrain in Terrain::ALL.iter() {
+            let (region, image_scale) = match terrain {
+                Terrain::Grass => (images.grass, 1.0),
+                Terrain::Road => (images.road, 1.0),
+                Terrain::Water => (images.water, 1.0),
+                Terrain::Fence => (images.fence, 1.0),
+                // The trees image is two tiles wide
+                Terrain::Tree => (images.trees, 0.5),
+            };
+            for y in 0..tiles.height {
+                for x in 0..tiles.width {
+                    if tiles.get(x, y) != terrain {
+                        continue;
+                    }
+                    let src = if terrain == Terrain::Grass {
+                        // The grass image covers 10 by 10 tiles
+                        Rect::new((x % 10) as f32 / 10.0, (y % 10) as f32 / 10.0, 0.1, 0.1)
+                    } else {
+                        Rect::one()
+                    };
+                    images.add(
+                        batch,
+                        region,
+                        DrawParam {
+                            src,
+                            dest: level_pixels(Point2::new(x as f32, y as f32)),
+                            scale: Point2::new(image_scale, image_scale),
+                            ..Default::default()
+                        },
+                    );
+                }
+            }
+        }
+    }
 
-        let offset_y = if self.player[1] < -1.0 {
-            self.player[1] + 1.0
-        } else if self.player[1] > LEVEL_HEIGHT as f32 - tiles_on_height {
-            self.player[1] - (LEVEL_HEIGHT as f32 - tiles_on_height)
-        } else {
-            0.0
+    /// Add the trees around the edge of the level, each turned to face the level.
+    fn add_border(batch: &mut SpriteBatch, images: &Images, world: &World) {
+        let width = world.width as f32;
+        let height = world.height as f32;
+        let mut add_tree = |position: Point2, rotation: f32| {
+            images.add(
+                batch,
+                images.trees,
+                DrawParam {
+                    dest: level_pixels(position),
+                    rotation,
+                    offset: Point2::new(0.5, 0.5),
+                    ..Default::default()
+                },
+            );
         };
+        for i in 0..(height / BORDER_TREE_SPACING).ceil() as u32 + 1 {
+            let y = BORDER_TREE_SPACING * i as f32;
+            add_tree(Point2::new(-0.8, y), 0.0);
+            add_tree(Point2::new(width - 0.2, y), PI);
+        }
+        for i in 0..(width / BORDER_TREE_SPACING).ceil() as u32 + 1 {
+            let x = BORDER_TREE_SPACING * i as f32;
+            add_tree(Point2::new(x, -0.8), 0.5 * PI);
+            add_tree(Point2::new(x, height - 0.2), 1.5 * PI);
+        }
+    }
 
-        let offset: Vector2 =
-            Game::float_coord_to_screen(ctx, Point2::new(offset_x, offset_y)).coords;
+    /// A camera looking at the player at the start of a game.
+    fn camera(ctx: &Context, world: &World) -> Camera {
+        Camera::new(
+            world.config.tiles_on_width,
+            Layout::new(ctx),
+            Vector2::new(world.width as f32, world.height as f32),
+            world.player,
+        )
+    }
 
-        let scale = Game::tile_size(ctx) / 32.0;
+    /// The player position to draw, interpolated between the last two ticks.
+    fn interpolated_player(&self) -> Point2 {
+        let alpha = self.time_accumulator / TICK_TIME;
+        self.previous_player + (self.world.player - self.previous_player) * alpha
+    }
 
-        for x in 0..LEVEL_WIDTH / 10 {
-            for y in 0..LEVEL_HEIGHT / 10 {
-                let pos = Point2::new(
-                    x as f32 * 10.0 - self.player[0],
-                    y as f32 * 10.0 - self.player[1],
-                );
-                let dest = Game::float_coord_to_screen(ctx, pos);
-                graphics::draw_ex(
-                    ctx,
-                    &self.images.grass,
-                    DrawParam {
-                        dest: dest + offset,
-                        rotation: 0.0,
-                        offset: Point2::new(0.0, 0.0),
-                        shear: Point2::new(0.0, 0.0),
-                        scale,
-                        ..Default::default()
-                    },
-                )?;
-            }
+    /// Fill the layers that change every frame.
+    fn fill_layers(&mut self) {
+        let images = self.images;
+        let player = level_pixels(self.interpolated_player());
+        let camera = &self.camera;
+        let world = &self.world;
+        for &layer in Layer::ALL.iter().filter(|layer| !layer.is_static()) {
+            self.layers[layer as usize].clear();
         }
 
-        // Draw houses
-        for house in &self.houses {
-            house.draw(ctx, &self, offset)?;
+        let objects = &mut self.layers[Layer::Objects as usize];
+        let visible_houses = world
+            .houses
+            .iter()
+            .filter(|house| camera.is_visible(house.position, 1.5));
+        for house in visible_houses {
+            house.add_sprites(objects, images, world.time as f32);
         }
-
-        // Draw letters
-        for letter in &self.letters {
-            letter.draw(ctx, &self, offset)?;
+        for obstacle in &world.obstacles {
+            obstacle.add_sprites(objects, images);
+        }
+        let visible_letters = world
+            .letters
+            .iter()
+            .filter(|letter| camera.is_visible(letter.position, 0.5));
+        for letter in visible_letters {
+            letter.add_sprites(objects, images, world.time);
         }
 
-        let player_pos = Point2::new(w / 2.0, h / 2.0);
-        let mut player_scale = scale.clone();
-        let image = match self.player_direction {
-            Direction::Left | Direction::DownLeft => &self.images.player_left,
-            Direction::Right | Direction::DownRight => {
-                player_scale[0] *= -1.0;
-                &self.images.player_left
-            }
-            Direction::Up | Direction::UpLeft | Direction::UpRight => &self.images.player_up,
-            _ => &self.images.player_front,
-        };
-        graphics::draw_ex(
-            ctx,
-            image,
+        let actors = &mut self.layers[Layer::Actors as usize];
+        images.add_frame(
+            actors,
+            self.postman.frame(&images.animations),
             DrawParam {
-                dest: player_pos + offset,
-                rotation: 0.0,
+                dest: player,
                 offset: Point2::new(0.5, 0.5),
-                shear: Point2::new(0.0, 0.0),
-                scale: player_scale,
                 ..Default::default()
             },
-        )?;
+        );
+        for animation in &self.letter_animation {
+            let flight = animation.time / LETTER_FLIGHT_TIME;
+            if flight < 1.0 {
+                images.add_clip(
+                    actors,
+                    "letter_held",
+                    0.0,
+                    DrawParam {
+                        dest: player + Vector2::new(0.0, -20.0 + flight * -32.0),
+                        offset: Point2::new(0.5, 0.5),
+                        ..Default::default()
+                    },
+                );
+            }
 
-        for animated_letter in &self.letter_animation {
-            self.images.letter.draw_ex(
-                ctx,
+            let popup = animation.time / SCORE_POPUP_TIME;
+            let combo = animation.delivery.combo.min(SCORE_POPUP_MAX_COMBO) - 1;
+            let scale = SCORE_POPUP_SCALE + SCORE_POPUP_COMBO_GROWTH * combo as f32;
+            let points = player + Vector2::new(0.0, -36.0 + popup * -24.0);
+            images.add_number(
+                actors,
+                animation.delivery.points,
                 DrawParam {
-                    dest: player_pos + Vector2::new(0.0, -20.0 + animated_letter * -32.0) + offset,
-                    rotation: 0.0,
-                    offset: Point2::new(0.5, 0.5),
+                    dest: points,
+                    scale: Point2::new(scale, scale),
+                    color: Some(graphics::Color::new(1.0, 0.85, 0.2, 1.0 - popup)),
                     ..Default::default()
                 },
-            )?;
+            );
+            // Several letters handed over at once show how many above the points
+            if animation.delivery.letters > 1 {
+                let line = SCORE_POPUP_LINE_HEIGHT * (scale + SCORE_POPUP_SCALE) / 2.0;
+                images.add_multiplier(
+                    actors,
+                    animation.delivery.letters,
+                    DrawParam {
+                        dest: points + Vector2::new(0.0, -line),
+                        scale: Point2::new(SCORE_POPUP_SCALE, SCORE_POPUP_SCALE),
+                        color: Some(graphics::Color::new(1.0, 1.0, 1.0, 1.0 - popup)),
+                        ..Default::default()
+                    },
+                );
+            }
         }
+    }
+}
+
+impl<'a> State<'a> for Game<'a> {
+    fn exit(&mut self, ctx: &mut Context, _shared: &mut Shared<'a>) -> GameResult<()> {
+        self.music.stop();
+        self.save_unfinished(ctx)
+    }
 
-        self.draw_trees(ctx, offset)?;
+    fn covered(&mut self, _ctx: &mut Context, _shared: &mut Shared<'a>) -> GameResult<()> {
+        self.music.pause();
+        Ok(())
+    }
 
-        // Draw picked up letters
-        for (i, letter) in self.holding_letters.iter().enumerate() {
-            letter.draw_in_hand(ctx, &self, i)?;
+    /// Coming back from the game over screen starts a new game.
+    fn uncovered(&mut self, ctx: &mut Context, shared: &mut Shared<'a>) -> GameResult<()> {
+        if self.world.game_over {
+            self.restart(ctx)?;
+        }
+        if shared.settings.music {
+            self.music.resume();
         }
+        Ok(())
+    }
 
-        self.images.arrow.draw_ex(
-            ctx,
-            DrawParam {
-                dest: Point2::new(scale[0] * 2.0 * 32.0, scale[0] * 1.0 * 32.0),
-                rotation: 0.0,
-                offset: Point2::new(0.5, 0.5),
-                shear: Point2::new(0.0, 0.0),
-                scale,
-                ..Default::default()
-            },
-        )?;
+    fn focus_lost(
+        &mut self,
+        ctx: &mut Context,
+        _shared: &mut Shared<'a>,
+    ) -> GameResult<Transition<'a>> {
+        Ok(Transition::Push(Box::new(PauseMenu::new(ctx)?)))
+    }
 
-        self.score_text
-            .draw(ctx, Point2::new(w - 100.0, 10.0), 0.0)?;
+    fn update(
+        &mut self,
+        ctx: &mut Context,
+        shared: &mut Shared<'a>,
+    ) -> GameResult<Transition<'a>> {
+        if !shared.settings.music {
+            self.music.stop();
+        } else if self.music.paused() {
+            self.music.resume();
+        } else if !self.music.playing() {
+            self.music.repeat();
+            self.music.play()?;
+        }
 
-        // Draw progress bar
-        self.images.progress_bar.draw_ex(
-            ctx,
-            DrawParam {
-                dest: Point2::new(w - 100.0, scale[0] * 1.0 * 32.0),
-                rotation: 0.0,
-                offset: Point2::new(0.0, 0.5),
-                shear: Point2::new(0.0, 0.0),
-                scale: Point2::new(scale[0] * 0.75, scale[1] / 2.0),
-                ..Default::default()
-            },
-        )?;
-        let fraction = ((self.letters.len() as f32
-            + self.time_since_last_letter / self.letter_spawn_time)/
-            MAX_LETTERS_ON_GROUND as f32).min(1.0);
-        self.images.progress_bar_filled.draw_ex(
-            ctx,
-            DrawParam {
-                src: Rect {
-                    x: 0.0,
-                    y: 0.0,
-                    w: fraction,
-                    h: 1.0,
-                },
-                dest: Point2::new(w - 100.0, scale[0] * 1.0 * 32.0),
-                rotation: 0.0,
-                offset: Point2::new(0.0, 0.5),
-                shear: Point2::new(0.0, 0.0),
-                scale: Point2::new(scale[0] * 0.75, scale[1] / 2.0),
-                ..Default::default()
-            },
-        )?;
+        let delta = timer::duration_to_f64(timer::get_delta(ctx)) as f32;
+        self.time_accumulator = (self.time_accumulator + delta).min(MAX_FRAME_TIME);
+        let mut input = Input {
+            direction: shared.controls.movement(),
+            bag: None,
+        };
+        while self.time_accumulator >= TICK_TIME && !self.world.game_over {
+            self.time_accumulator -= TICK_TIME;
+            // The bag is only used by the first tick after asking
+            input.bag = self.bag_action.take();
+            self.tick(ctx, input, shared.settings.sounds)?;
+        }
+        let player = self.interpolated_player();
+        self.camera.set_layout(Layout::new(ctx));
+        self.camera.update(player, delta);
+        self.hud.update(ctx, &self.world, &self.camera)?;
+        if shared.settings.minimap {
+            self.minimap.update(ctx, self.images, &self.world)?;
+        }
 
-        if self.game_over {
-            self.game_over_text.draw_ex(ctx,
-                DrawParam {
-                    dest: Point2::new(400.0, 300.0),
-                    offset: Point2::new(0.5, 0.5),
-                    scale: Point2::new(2.0, 2.0),
-                    ..Default::default()
-                }
+        if self.world.game_over {
+            let game_over = GameOver::new(
+                ctx,
+                shared,
+                self.world.scoring.breakdown,
+                self.world.seed,
+                self.world.time,
+                self.playback.is_none(),
+                self.replay_check.take(),
             )?;
+            return Ok(Transition::Push(Box::new(game_over)));
         }
+        Ok(Transition::None)
+    }
 
-        graphics::present(ctx);
+    fn draw(&mut self, ctx: &mut Context, shared: &Shared<'a>) -> GameResult<()> {
+        self.fill_layers();
+        let param = self.camera.level_param();
+        for batch in &self.layers {
+            graphics::draw_ex(ctx, batch, param)?;
+        }
+        self.hud.draw(ctx, self.images, self.world.time as f32)?;
+        if shared.settings.minimap {
+            self.minimap.draw(ctx)?;
+        }
         Ok(())
     }
 
-    fn key_down_event(
+    fn input(
         &mut self,
         ctx: &mut Context,
-        keycode: Keycode,
-        _keymod: Mod,
-        _repeat: bool,
-    ) {
-        if self.game_over {
-            if keycode == Keycode::Space {
-                self.restart(ctx).unwrap();
-            }
+        shared: &mut Shared<'a>,
+        event: &InputEvent,
+    ) -> GameResult<Transition<'a>> {
+        // Movement is read from the held controls every tick
+        Ok(if shared.controls.pressed(event, Action::Pause) {
+            Transition::Push(Box::new(PauseMenu::new(ctx)?))
         } else {
-            match keycode {
-                Keycode::Left | Keycode::A => self.left_pressed = true,
-                Keycode::Right | Keycode::D => self.right_pressed = true,
-                Keycode::Up | Keycode::W => self.up_pressed = true,
-                Keycode::Down | Keycode::S => self.down_pressed = true,
-                _ => {}
+            if shared.controls.pressed(event, Action::ZoomIn) {
+                self.camera.zoom_in();
+            } else if shared.controls.pressed(event, Action::ZoomOut) {
+                self.camera.zoom_out();
+            } else if shared.controls.pressed(event, Action::Minimap) {
+                shared.settings.minimap = !shared.settings.minimap;
+            } else if shared.controls.pressed(event, Action::RotateBag) {
+                self.bag_action = Some(BagAction::Rotate);
+            } else if shared.controls.pressed(event, Action::SwapLetters) {
+                self.bag_action = Some(BagAction::Swap);
+            } else if shared.controls.pressed(event, Action::DropLetter) {
+                self.bag_action = Some(BagAction::Drop);
             }
-            self.calculate_player_direction();
-        }
-    }
-
-    fn key_up_event(&mut self, _ctx: &mut Context, keycode: Keycode, _keymod: Mod, _repeat: bool)
-    {
-        if !self.game_over {
-            match keycode {
-                Keycode::Left | Keycode::A => self.left_pressed = false,
-                Keycode::Right | Keycode::D => self.right_pressed = false,
-                Keycode::Up | Keycode::W => self.up_pressed = false,
-                Keycode::Down | Keycode::S => self.down_pressed = false,
-                _ => {}
-            }
-            self.calculate_player_direction();
-        }
+            Transition::None
+        })
     }
 }
@@ -0,0 +1,221 @@
+use ggez::event::Keycode;
+use ggez::graphics::{DrawParam, Drawable, Point2, Text, Vector2};
+use ggez::*;
+
+use game_state::{InputEvent, Shared, State, Transition};
+use highscores::{self, HighScore, MAX_NAME_LENGTH};
+use input::Action;
+use layout::{Anchor, Layout};
+use menu::{self, MainMenu};
+use scoring::Breakdown;
+use ui;
+
+/// Shown on top of a finished game. Asks for a name when the score made it into the high
+/// scores, then shows the table. Leaving it restarts the game below it.
+pub struct GameOver {
+    score: u32,
+    seed: u64,
+    duration: f64,
+
+    /// The name typed so far, while the player enters a new high score.
+    name_entry: Option<String>,
+    name_entry_text: Text,
+    /// Where the score ended up in the table.
+    place: Option<usize>,
+    high_score_texts: Vec<Text>,
+
+    game_over_text: Text,
+    seed_text: Text,
+    /// Where the points came from.
+    breakdown_text: Text,
+    /// Whether a replay that was played back ended as recorded.
+    replay_check_text: Option<Text>,
+}
+
+impl GameOver {
+    /// `record` is whether the score may go into the high scores, which isn't the case for
+    /// games that are played back from a replay. `replay_check` tells how such a replay ended.
+    pub fn new(
+        ctx: &mut Context,
+        shared: &Shared,
+        breakdown: Breakdown,
+        seed: u64,
+        duration: f64,
+        record: bool,
+        replay_check: Option<String>,
+    ) -> GameResult<GameOver> {
+        let score = breakdown.total();
+        let name_entry = if record && shared.high_scores.qualifies(score) {
+            Some(String::new())
+        } else {
+            None
+        };
+        Ok(GameOver {
+            score,
+            seed,
+            duration,
+
+            name_entry,
+            name_entry_text: GameOver::get_name_entry_text(ctx, "")?,
+            place: None,
+            high_score_texts: highscores::table_texts(ctx, &shared.high_scores)?,
+
+            game_over_text: ui::text(
+                ctx,
+                "Game over - press Confirm to restart, or Back for the menu",
+            )?,
+            seed_text: ui::text(ctx, &format!("Seed: {}", seed))?,
+            breakdown_text: GameOver::get_breakdown_text(ctx, &breakdown)?,
+            replay_check_text: match replay_check {
+                Some(check) => Some(ui::text(ctx, &check)?),
+                None => None,
+            },
+        })
+    }
+
+    fn get_breakdown_text(ctx: &mut Context, breakdown: &Breakdown) -> GameResult<Text> {
+        ui::text(
+            ctx,
+            &format!(
+                "Letters {} + on time {} + speed {} + multiple {} + combos {} - broken {} = {}",
+                breakdown.letters,
+                breakdown.on_time,
+                breakdown.speed,
+                breakdown.multiplier,
+                breakdown.combo,
+                breakdown.penalties,
+                breakdown.total()
+            ),
+        )
+    }
+
+    fn get_name_entry_text(ctx: &mut Context, name: &str) -> GameResult<Text> {
+        ui::text(
+            ctx,
+            &format!("New high score! Type your name and press Enter: {}_", name),
+        )
+    }
+
+    /// Add the score to the high scores under the entered name.
+    fn save_high_score(&mut self, ctx: &mut Context, shared: &mut Shared) -> GameResult<()> {
+        let name = match self.name_entry.take() {
+            Some(name) => name,
+            None => return Ok(()),
+        };
+        let entry = HighScore::new(
+            self.score,
+            &highscores::clean_name(&name),
+            self.seed,
+            self.duration,
+        );
+        self.place = shared.high_scores.insert(entry);
+        if let Err(e) = shared.high_scores.save(ctx) {
+            println!("Could not save the high scores: {}", e);
+        }
+        self.high_score_texts = highscores::table_texts(ctx, &shared.high_scores)?;
+        Ok(())
+    }
+}
+
+impl<'a> State<'a> for GameOver {
+    fn is_overlay(&self) -> bool {
+        true
+    }
+
+    fn draw(&mut self, ctx: &mut Context, _shared: &Shared<'a>) -> GameResult<()> {
+        menu::dim_screen(ctx)?;
+        let layout = Layout::new(ctx);
+        let centred = DrawParam {
+            offset: Point2::new(0.5, 0.5),
+            ..Default::default()
+        };
+        self.game_over_text.draw_ex(
+            ctx,
+            layout.place(
+                Anchor::Top,
+                Vector2::new(0.0, 140.0),
+                DrawParam {
+                    scale: Point2::new(1.5, 1.5),
+                    ..centred
+                },
+            ),
+        )?;
+        self.seed_text.draw_ex(
+            ctx,
+            layout.place(Anchor::Top, Vector2::new(0.0, 180.0), centred),
+        )?;
+        if let Some(ref text) = self.replay_check_text {
+            text.draw_ex(
+                ctx,
+                layout.place(Anchor::Top, Vector2::new(0.0, 100.0), centred),
+            )?;
+        }
+        self.breakdown_text.draw_ex(
+            ctx,
+            layout.place(Anchor::Top, Vector2::new(0.0, 205.0), centred),
+        )?;
+        if self.name_entry.is_some() {
+            self.name_entry_text.draw_ex(
+                ctx,
+                layout.place(Anchor::Top, Vector2::new(0.0, 260.0), centred),
+            )?;
+        } else {
+            highscores::draw_table(
+                ctx,
+                &self.high_score_texts,
+                Vector2::new(0.0, 240.0),
+                self.place,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn input(
+        &mut self,
+        ctx: &mut Context,
+        shared: &mut Shared<'a>,
+        event: &InputEvent,
+    ) -> GameResult<Transition<'a>> {
+        match self.name_entry {
+            Some(ref mut name) => match *event {
+                InputEvent::Text(ref text) => {
+                    for c in text.chars().filter(|c| !c.is_control()) {
+                        if name.chars().count() < MAX_NAME_LENGTH {
+                            name.push(c);
+                        }
+                    }
+                    self.name_entry_text = GameOver::get_name_entry_text(ctx, name)?;
+                    return Ok(Transition::None);
+                }
+                InputEvent::KeyDown {
+                    keycode: Keycode::Backspace,
+                    ..
+                } => {
+                    name.pop();
+                    self.name_entry_text = GameOver::get_name_entry_text(ctx, name)?;
+                    return Ok(Transition::None);
+                }
+                InputEvent::KeyDown {
+                    keycode: Keycode::Return,
+                    ..
+                }
+                | InputEvent::KeyDown {
+                    keycode: Keycode::KpEnter,
+                    ..
+                } => {}
+                _ => return Ok(Transition::None),
+            },
+            None => {
+                return Ok(if shared.controls.pressed(event, Action::Confirm) {
+                    Transition::Pop
+                } else if shared.controls.pressed(event, Action::Back) {
+                    Transition::Clear(Box::new(MainMenu::new(ctx)?))
+                } else {
+                    Transition::None
+                });
+            }
+        }
+        self.save_high_score(ctx, shared)?;
+        Ok(Transition::None)
+    }
+}
@@ -1,72 +1,329 @@
-use game::Game;
-use ggez::event::{EventHandler, Keycode, Mod};
+use game::{Game, GameOptions};
+use ggez::event::{Axis, Button, EventHandler, Keycode, Mod};
 use ggez::*;
+use highscores::HighScores;
 use images::Images;
+use input::{self, Action, Bindings, Controls};
+use layout;
+use menu::MainMenu;
+use replay::{Playback, Replay};
 use splash_screen::SplashScreen;
+use window::WindowSettings;
 
-enum GameState<'a> {
-    MainGame(Game<'a>),
-    SplashScreen(SplashScreen<'a>),
+/// A key, gamepad or text event, passed to the state on top of the stack.
+#[derive(Clone, Debug)]
+pub enum InputEvent {
+    KeyDown {
+        keycode: Keycode,
+        repeat: bool,
+    },
+    KeyUp {
+        keycode: Keycode,
+        repeat: bool,
+    },
+    ButtonDown(Button),
+    ButtonUp(Button),
+    /// A gamepad axis moved, to a value from -1 to 1.
+    AxisMoved(Axis, f32),
+    Text(String),
 }
 
-pub struct StateHolder<'a> {
-    game_state: GameState<'a>,
+/// Settings the player can change from the options menu.
+#[derive(Clone, Copy, Debug)]
+pub struct Settings {
+    pub music: bool,
+    pub sounds: bool,
+    /// Whether the map of the level is shown during a game.
+    pub minimap: bool,
 }
 
-impl<'a> StateHolder<'a> {
-    pub fn startup(ctx: &mut Context, images: &'a Images) -> GameResult<StateHolder<'a>> {
-        Ok(StateHolder {
-            game_state: GameState::SplashScreen(SplashScreen::new(ctx, images)?),
-        })
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            music: true,
+            sounds: true,
+            minimap: true,
+        }
     }
 }
 
-impl<'a> EventHandler for StateHolder<'a> {
-    fn update(&mut self, ctx: &mut Context) -> GameResult<()> {
-        let mut next_state = None;
+/// Everything the states have in common, owned by the `StateHolder`.
+pub struct Shared<'a> {
+    pub images: &'a Images,
+    pub options: GameOptions,
+    pub high_scores: HighScores,
+    pub settings: Settings,
+    pub window: WindowSettings,
+    pub controls: Controls,
+}
+
+/// What the state on top of the stack wants to happen next.
+pub enum Transition<'a> {
+    None,
+    /// Put a state on top of the current one. If the new state is an overlay, the current one
+    /// is still drawn underneath it.
+    Push(Box<dyn State<'a> + 'a>),
+    /// Leave the current state and go back to the one below it.
+    Pop,
+    /// Leave the current state for another one.
+    Replace(Box<dyn State<'a> + 'a>),
+    /// Leave every state and start over with a new one.
+    Clear(Box<dyn State<'a> + 'a>),
+    Quit,
+}
+
+/// A screen of the game, such as a menu or the game itself.
+///
+/// Only the state on top of the stack is updated and receives input. The hooks are called when
+/// the state is put on the stack (`enter`), removed from it (`exit`), and when another state is
+/// put on top of it (`covered`) or removed from on top of it (`uncovered`).
+pub trait State<'a> {
+    fn enter(&mut self, _ctx: &mut Context, _shared: &mut Shared<'a>) -> GameResult<()> {
+        Ok(())
+    }
+
+    fn exit(&mut self, _ctx: &mut Context, _shared: &mut Shared<'a>) -> GameResult<()> {
+        Ok(())
+    }
+
+    fn covered(&mut self, _ctx: &mut Context, _shared: &mut Shared<'a>) -> GameResult<()> {
+        Ok(())
+    }
+
+    fn uncovered(&mut self, _ctx: &mut Context, _shared: &mut Shared<'a>) -> GameResult<()> {
+        Ok(())
+    }
+
+    /// Whether the state below this one should be drawn underneath it.
+    fn is_overlay(&self) -> bool {
+        false
+    }
+
+    /// Whether every key and button should go to this state, even the ones that toggle
+    /// fullscreen.
+    fn captures_input(&self) -> bool {
+        false
+    }
+
+    fn update(
+        &mut self,
+        _ctx: &mut Context,
+        _shared: &mut Shared<'a>,
+    ) -> GameResult<Transition<'a>> {
+        Ok(Transition::None)
+    }
+
+    /// Called when the window loses focus while this state is on top.
+    fn focus_lost(
+        &mut self,
+        _ctx: &mut Context,
+        _shared: &mut Shared<'a>,
+    ) -> GameResult<Transition<'a>> {
+        Ok(Transition::None)
+    }
+
+    fn draw(&mut self, ctx: &mut Context, shared: &Shared<'a>) -> GameResult<()>;
+
+    fn input(
+        &mut self,
+        ctx: &mut Context,
+        shared: &mut Shared<'a>,
+        event: &InputEvent,
+    ) -> GameResult<Transition<'a>>;
+}
+
+/// Runs a stack of states, of which only the top one is active.
+pub struct StateHolder<'a> {
+    states: Vec<Box<dyn State<'a> + 'a>>,
+    shared: Shared<'a>,
+}
+
+impl<'a> StateHolder<'a> {
+    pub fn startup(
+        ctx: &mut Context,
+        images: &'a Images,
+        options: GameOptions,
+        replay: Option<Replay>,
+    ) -> GameResult<StateHolder<'a>> {
+        let window = WindowSettings::load(ctx);
+        window.apply(ctx)?;
+        let mut holder = StateHolder {
+            states: Vec::new(),
+            shared: Shared {
+                images,
+                options,
+                high_scores: HighScores::load(ctx),
+                settings: Settings::default(),
+                window,
+                controls: Controls::new(Bindings::load(ctx)),
+            },
+        };
+        match replay {
+            // Replays skip the splash screen, so they start on the first tick of the game
+            Some(replay) => {
+                let menu = MainMenu::new(ctx)?;
+                holder.apply(ctx, Transition::Push(Box::new(menu)))?;
+                let playback = Some(Playback::new(replay));
+                let game = Game::new(ctx, images, holder.shared.options.clone(), playback)?;
+                holder.apply(ctx, Transition::Push(Box::new(game)))?;
+            }
+            None => {
+                let splash_screen = SplashScreen::new(ctx, images)?;
+                holder.apply(ctx, Transition::Push(Box::new(splash_screen)))?;
+            }
+        }
+        Ok(holder)
+    }
 
-        match self.game_state {
-            GameState::SplashScreen(ref mut splash_screen) => {
-                splash_screen.update(ctx)?;
-                if splash_screen.should_start() {
-                    next_state = Some(GameState::MainGame(Game::new(ctx, splash_screen.images)?));
+    fn apply(&mut self, ctx: &mut Context, transition: Transition<'a>) -> GameResult<()> {
+        match transition {
+            Transition::None => {}
+            Transition::Push(mut state) => {
+                if let Some(top) = self.states.last_mut() {
+                    top.covered(ctx, &mut self.shared)?;
                 }
+                state.enter(ctx, &mut self.shared)?;
+                self.states.push(state);
             }
-            GameState::MainGame(ref mut game) => game.update(ctx)?,
+            Transition::Pop => {
+                if let Some(mut top) = self.states.pop() {
+                    top.exit(ctx, &mut self.shared)?;
+                }
+                match self.states.last_mut() {
+                    Some(top) => top.uncovered(ctx, &mut self.shared)?,
+                    None => ctx.quit()?,
+                }
+            }
+            Transition::Replace(mut state) => {
+                if let Some(mut top) = self.states.pop() {
+                    top.exit(ctx, &mut self.shared)?;
+                }
+                state.enter(ctx, &mut self.shared)?;
+                self.states.push(state);
+            }
+            Transition::Clear(mut state) => {
+                self.exit_all(ctx)?;
+                state.enter(ctx, &mut self.shared)?;
+                self.states.push(state);
+            }
+            Transition::Quit => ctx.quit()?,
+        }
+        Ok(())
+    }
+
+    /// Leave every state, from the top down.
+    fn exit_all(&mut self, ctx: &mut Context) -> GameResult<()> {
+        while let Some(mut top) = self.states.pop() {
+            top.exit(ctx, &mut self.shared)?;
         }
+        Ok(())
+    }
 
-        if let Some(state) = next_state {
-            self.game_state = state;
+    /// Pass an input event to the state on top, the single path all input goes through. The
+    /// held controls are kept track of first, whichever state is on top, and fullscreen is
+    /// toggled from any state that doesn't capture the input.
+    fn dispatch(&mut self, ctx: &mut Context, event: InputEvent) -> GameResult<()> {
+        self.shared.controls.handle(&event);
+        let captured = self.states.last().map_or(false, |top| top.captures_input());
+        if !captured && self.shared.controls.pressed(&event, Action::Fullscreen) {
+            return self.toggle_fullscreen(ctx);
         }
+        let transition = match self.states.last_mut() {
+            Some(top) => top.input(ctx, &mut self.shared, &event)?,
+            None => Transition::None,
+        };
+        self.apply(ctx, transition)
+    }
+
+    fn toggle_fullscreen(&mut self, ctx: &mut Context) -> GameResult<()> {
+        self.shared.window.toggle_fullscreen(ctx)?;
+        self.save_window(ctx);
         Ok(())
     }
 
+    /// Save the window settings. The window keeps working as it is if that fails.
+    fn save_window(&self, ctx: &mut Context) {
+        if let Err(e) = self.shared.window.save(ctx) {
+            println!("Could not save the window settings: {}", e);
+        }
+    }
+}
+
+impl<'a> EventHandler for StateHolder<'a> {
+    fn update(&mut self, ctx: &mut Context) -> GameResult<()> {
+        let transition = match self.states.last_mut() {
+            Some(top) => top.update(ctx, &mut self.shared)?,
+            None => Transition::None,
+        };
+        self.apply(ctx, transition)
+    }
+
     fn draw(&mut self, ctx: &mut Context) -> GameResult<()> {
-        match self.game_state {
-            GameState::SplashScreen(ref mut splash_screen) => {
-                splash_screen.draw(ctx)?;
-            }
-            GameState::MainGame(ref mut game) => game.draw(ctx)?,
+        graphics::clear(ctx);
+        // Draw from the topmost state that covers the whole screen
+        let bottom = self
+            .states
+            .iter()
+            .rposition(|state| !state.is_overlay())
+            .unwrap_or(0);
+        for state in &mut self.states[bottom..] {
+            state.draw(ctx, &self.shared)?;
         }
+        graphics::present(ctx);
         timer::yield_now();
         Ok(())
     }
 
-    fn key_down_event(&mut self, ctx: &mut Context, keycode: Keycode, keymod: Mod, repeat: bool) {
-        match self.game_state {
-            GameState::SplashScreen(ref mut splash_screen) => {
-                splash_screen.key_down_event(ctx, keycode, keymod, repeat)
-            }
-            GameState::MainGame(ref mut game) => game.key_down_event(ctx, keycode, keymod, repeat),
+    fn key_down_event(&mut self, ctx: &mut Context, keycode: Keycode, _keymod: Mod, repeat: bool) {
+        self.dispatch(ctx, InputEvent::KeyDown { keycode, repeat })
+            .unwrap();
+    }
+
+    fn key_up_event(&mut self, ctx: &mut Context, keycode: Keycode, _keymod: Mod, repeat: bool) {
+        self.dispatch(ctx, InputEvent::KeyUp { keycode, repeat })
+            .unwrap();
+    }
+
+    fn text_input_event(&mut self, ctx: &mut Context, text: String) {
+        self.dispatch(ctx, InputEvent::Text(text)).unwrap();
+    }
+
+    fn controller_button_down_event(&mut self, ctx: &mut Context, button: Button, _id: i32) {
+        self.dispatch(ctx, InputEvent::ButtonDown(button)).unwrap();
+    }
+
+    fn controller_button_up_event(&mut self, ctx: &mut Context, button: Button, _id: i32) {
+        self.dispatch(ctx, InputEvent::ButtonUp(button)).unwrap();
+    }
+
+    fn controller_axis_event(&mut self, ctx: &mut Context, axis: Axis, value: i16, _id: i32) {
+        let value = input::axis_value(value);
+        self.dispatch(ctx, InputEvent::AxisMoved(axis, value))
+            .unwrap();
+    }
+
+    fn resize_event(&mut self, ctx: &mut Context, width: u32, height: u32) {
+        self.shared.window.resized(width, height);
+        layout::fit_screen(ctx).unwrap();
+    }
+
+    fn quit_event(&mut self, ctx: &mut Context) -> bool {
+        self.save_window(ctx);
+        // Let the states save what they need to, such as the replay of a game in progress
+        if let Err(e) = self.exit_all(ctx) {
+            println!("Could not leave the game cleanly: {}", e);
         }
+        false
     }
 
-    fn key_up_event(&mut self, ctx: &mut Context, keycode: Keycode, keymod: Mod, repeat: bool) {
-        match self.game_state {
-            GameState::SplashScreen(ref mut splash_screen) => {
-                splash_screen.key_up_event(ctx, keycode, keymod, repeat)
-            }
-            GameState::MainGame(ref mut game) => game.key_up_event(ctx, keycode, keymod, repeat),
+    fn focus_event(&mut self, ctx: &mut Context, gained: bool) {
+        if gained {
+            return;
         }
+        let transition = match self.states.last_mut() {
+            Some(top) => top.focus_lost(ctx, &mut self.shared).unwrap(),
+            None => Transition::None,
+        };
+        self.apply(ctx, transition).unwrap();
     }
 }
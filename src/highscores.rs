@@ -0,0 +1,311 @@
+use ggez::graphics::{DrawParam, Drawable, FilterMode, Point2, Text, Vector2};
+use ggez::{graphics, Context, GameResult};
+
+use layout::{Anchor, Layout};
+
+use std::io::{Read, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Where the table is stored, in the user config directory.
+const PATH: &str = "/highscores.txt";
+
+/// The first line of the file. The number is raised whenever the format changes, and files with
+/// another version are ignored.
+const HEADER: &str = "too-much-post highscores 1";
+
+/// How many scores are kept.
+pub const MAX_ENTRIES: usize = 10;
+
+/// The longest name that can be entered, in characters.
+pub const MAX_NAME_LENGTH: usize = 16;
+
+#[derive(Clone, Debug)]
+pub struct HighScore {
+    pub score: u32,
+    pub name: String,
+    /// When the game ended, in seconds since the Unix epoch.
+    pub date: u64,
+    pub seed: u64,
+    /// How long the game lasted in seconds.
+    pub duration: f64,
+}
+
+impl HighScore {
+    pub fn new(score: u32, name: &str, seed: u64, duration: f64) -> HighScore {
+        let date = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_secs())
+            .unwrap_or(0);
+        HighScore {
+            score,
+            name: name.to_string(),
+            date,
+            seed,
+            duration,
+        }
+    }
+
+    /// Read an entry from a line of the file, or `None` if the line is damaged.
+    fn parse(line: &str) -> Option<HighScore> {
+        let fields: Vec<&str> = line.splitn(5, ' ').collect();
+        if fields.len() != 5 {
+            return None;
+        }
+        let duration: f64 = fields[3].parse().ok()?;
+        if !(duration >= 0.0) {
+            return None;
+        }
+        Some(HighScore {
+            score: fields[0].parse().ok()?,
+            date: fields[1].parse().ok()?,
+            seed: fields[2].parse().ok()?,
+            duration,
+            name: clean_name(fields[4]),
+        })
+    }
+
+    /// The date the game ended as `year-month-day`, in UTC.
+    fn date_text(&self) -> String {
+        // Convert days since the epoch to a civil date, following Howard Hinnant's algorithm
+        let days = (self.date / 86_400) as i64 + 719_468;
+        let era = days / 146_097;
+        let day_of_era = days - era * 146_097;
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month_index = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+        let month = if month_index < 10 {
+            month_index + 3
+        } else {
+            month_index - 9
+        };
+        let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+        format!("{}-{:02}-{:02}", year, month, day)
+    }
+}
+
+/// Make a name fit on a single line of the table and of the file.
+pub fn clean_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_NAME_LENGTH)
+        .collect();
+    let name = name.trim();
+    if name.is_empty() {
+        "Anonymous".to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+/// The best scores on this computer, highest first.
+#[derive(Clone, Debug, Default)]
+pub struct HighScores {
+    pub entries: Vec<HighScore>,
+}
+
+impl HighScores {
+    /// Load the table from the user config directory. A missing or damaged file never stops the
+    /// game: whatever can't be read is left out, and the table starts empty if nothing can.
+    pub fn load(ctx: &mut Context) -> HighScores {
+        if !ctx.filesystem.exists(PATH) {
+            return HighScores::default();
+        }
+        let mut source = String::new();
+        let read = ctx
+            .filesystem
+            .open(PATH)
+            .and_then(|mut file| Ok(file.read_to_string(&mut source)?));
+        match read {
+            Ok(_) => HighScores::parse(&source),
+            Err(e) => {
+                println!("Could not read the high scores: {}", e);
+                HighScores::default()
+            }
+        }
+    }
+
+    pub fn parse(source: &str) -> HighScores {
+        let mut lines = source.lines();
+        match lines.next() {
+            Some(header) if header == HEADER => {}
+            _ => {
+                println!("Ignoring the high scores, because they are in an unknown format");
+                return HighScores::default();
+            }
+        }
+        let mut high_scores = HighScores::default();
+        for line in lines.filter(|line| !line.trim().is_empty()) {
+            match HighScore::parse(line) {
+                Some(entry) => high_scores.entries.push(entry),
+                None => println!("Skipping damaged high score \"{}\"", line),
+            }
+        }
+        high_scores.sort();
+        high_scores
+    }
+
+    pub fn save(&self, ctx: &mut Context) -> GameResult<()> {
+        let file = ctx.filesystem.create(PATH)?;
+        self.write(file)
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> GameResult<()> {
+        writeln!(writer, "{}", HEADER)?;
+        for entry in &self.entries {
+            writeln!(
+                writer,
+                "{} {} {} {} {}",
+                entry.score, entry.date, entry.seed, entry.duration, entry.name
+            )?;
+        }
+        Ok(())
+    }
+
+    fn sort(&mut self) {
+        // Earlier scores stay ahead of later ones with the same score
+        self.entries
+            .sort_by(|a, b| b.score.cmp(&a.score).then(a.date.cmp(&b.date)));
+        self.entries.truncate(MAX_ENTRIES);
+    }
+
+    /// Whether the score is good enough to get into the table.
+    pub fn qualifies(&self, score: u32) -> bool {
+        score > 0
+            && (self.entries.len() < MAX_ENTRIES
+                || self.entries.iter().any(|entry| entry.score < score))
+    }
+
+    /// Add an entry, and return its place in the table if it stayed in.
+    pub fn insert(&mut self, entry: HighScore) -> Option<usize> {
+        let place = self
+            .entries
+            .iter()
+            .position(|other| other.score < entry.score)
+            .unwrap_or(self.entries.len());
+        self.entries.insert(place, entry);
+        self.entries.truncate(MAX_ENTRIES);
+        if place < self.entries.len() {
+            Some(place)
+        } else {
+            None
+        }
+    }
+
+    /// The lines of the table as they are shown on screen.
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = vec!["High scores".to_string()];
+        if self.entries.is_empty() {
+            lines.push("No scores yet".to_string());
+        }
+        for (i, entry) in self.entries.iter().enumerate() {
+            lines.push(format!(
+                "{:>2}. {:>4}  {:<16}  {}  {:>4.0}s  seed {}",
+                i + 1,
+                entry.score,
+                entry.name,
+                entry.date_text(),
+                entry.duration,
+                entry.seed
+            ));
+        }
+        lines
+    }
+}
+
+/// The table as text, ready to be drawn with `draw_table`.
+pub fn table_texts(ctx: &mut Context, high_scores: &HighScores) -> GameResult<Vec<Text>> {
+    let mut texts = Vec::new();
+    for line in high_scores.lines() {
+        let mut text = Text::new(ctx, &line, &graphics::Font::default_font()?)?;
+        text.set_filter(FilterMode::Nearest);
+        texts.push(text);
+    }
+    Ok(texts)
+}
+
+/// Draw the table with its title `offset` reference pixels from the top centre of the screen.
+/// The entry at `highlight` is drawn in yellow.
+pub fn draw_table(
+    ctx: &mut Context,
+    texts: &[Text],
+    offset: Vector2,
+    highlight: Option<usize>,
+) -> GameResult<()> {
+    let layout = Layout::new(ctx);
+    for (i, text) in texts.iter().enumerate() {
+        let color = if i > 0 && highlight == Some(i - 1) {
+            graphics::Color::new(1.0, 1.0, 0.0, 1.0)
+        } else {
+            graphics::Color::new(1.0, 1.0, 1.0, 1.0)
+        };
+        text.draw_ex(
+            ctx,
+            layout.place(
+                Anchor::Top,
+                offset + Vector2::new(0.0, 20.0 * i as f32),
+                DrawParam {
+                    offset: Point2::new(0.5, 0.0),
+                    color: Some(color),
+                    ..Default::default()
+                },
+            ),
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(score: u32, name: &str, date: u64) -> HighScore {
+        HighScore {
+            score,
+            name: name.to_string(),
+            date,
+            seed: 1234,
+            duration: 61.5,
+        }
+    }
+
+    #[test]
+    fn table_round_trips() {
+        let mut high_scores = HighScores::default();
+        high_scores.insert(entry(10, "Pat", 1_500_000_000));
+        high_scores.insert(entry(30, "Sam the postman", 1_500_000_100));
+        let mut file = Vec::new();
+        high_scores.write(&mut file).unwrap();
+
+        let read = HighScores::parse(&String::from_utf8(file).unwrap());
+        assert_eq!(read.entries.len(), 2);
+        for (read, written) in read.entries.iter().zip(&high_scores.entries) {
+            assert_eq!(read.score, written.score);
+            assert_eq!(read.name, written.name);
+            assert_eq!(read.date, written.date);
+            assert_eq!(read.seed, written.seed);
+            assert_eq!(read.duration, written.duration);
+        }
+    }
+
+    #[test]
+    fn damaged_lines_are_skipped() {
+        let source = format!(
+            "{}\n20 1500000000 5 30 Pat\nnot a score\n\n5 1500000000 5 -1 Negative\n\
+             40 1500000000 5 12.5 Sam\n",
+            HEADER
+        );
+        let high_scores = HighScores::parse(&source);
+        let scores: Vec<u32> = high_scores
+            .entries
+            .iter()
+            .map(|entry| entry.score)
+            .collect();
+        assert_eq!(scores, vec![40, 20]);
+
+        let old = "too-much-post highscores 0\n20 1500000000 5 30 Pat\n";
+        assert!(HighScores::parse(old).entries.is_empty());
+    }
+}
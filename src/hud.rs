@@ -0,0 +1,362 @@
+use ggez::graphics::{Color, Point2, Vector2};
+use ggez::{Context, GameResult};
+use std::f32::consts::PI;
+
+use animation;
+use atlas::Region;
+use camera::{Camera, TILE_PIXELS};
+use images::Images;
+use layout::{Anchor, Layout, REFERENCE_WIDTH};
+use ui::{Direction, Label, ProgressBar, Sprite, SpriteSource, StackPanel, Ui, Widget, WidgetId};
+use world::{LetterKind, World, HOUSE_HALF_SIZE};
+
+/// How much of a letter in the bag is covered by the next one, as a fraction of its height.
+const BAG_OVERLAP: f32 = 0.35;
+
+/// The space between the edge of the screen and the letters in the bag, in reference pixels.
+const MARGIN: f32 = 4.0;
+
+/// How far the pointers at things off the screen stay from its edges, in reference pixels.
+const POINTER_MARGIN: f32 = 24.0;
+/// How far the distance to the house is shown from its pointer, towards the middle of the
+/// screen, in reference pixels.
+const DISTANCE_GAP: f32 = 28.0;
+/// The size of the pointers at letters, compared to the one at the house.
+const LETTER_POINTER_SIZE: f32 = 0.75;
+
+/// The colour of the pointers at letters when they appear, when they are halfway to being due
+/// and when they are due. Letters in the bag turn urgent when they are overdue.
+const CALM: [f32; 3] = [0.5, 1.0, 0.5];
+const WORRYING: [f32; 3] = [1.0, 0.9, 0.3];
+const URGENT: [f32; 3] = [1.0, 0.25, 0.25];
+
+/// What is shown over the game: the letters in the bag, the score, how full the ground is, the
+/// letter that has been waiting the longest and where the things off the screen are.
+///
+/// The widgets only change when what they show changes, so new items are added here without
+/// touching the drawing of the world.
+pub struct Hud {
+    ui: Ui,
+    /// The letters in the bag, from the front.
+    bag: WidgetId,
+    score: WidgetId,
+    ground: WidgetId,
+    /// The letter that has been waiting the longest, and how long.
+    oldest: WidgetId,
+    /// How far handing over a registered letter is, shown over the house.
+    handover: WidgetId,
+    /// Points at the house the front letter goes to while it is off the screen.
+    house_pointer: WidgetId,
+    house_distance: WidgetId,
+    /// Point at the letters on the ground that are off the screen. More are added when there
+    /// are not enough, and the ones that are not needed are hidden.
+    letter_pointers: Vec<WidgetId>,
+    pointer: Region,
+    /// The size of the sprites, which matches the size of the level at the normal zoom.
+    scale: f32,
+
+    /// What the widgets show now, to tell when they need to change.
+    /// The number, kind and whether it is overdue of every letter in the bag.
+    shown_bag: Vec<(u32, LetterKind, bool)>,
+    /// The number and kind of the oldest letter, its age in whole seconds and whether it is
+    /// overdue.
+    shown_oldest: Option<(u32, LetterKind, u32, bool)>,
+    shown_score: u32,
+    shown_distance: Option<u32>,
+}
+
+impl Hud {
+    pub fn new(
+        ctx: &mut Context,
+        images: &Images,
+        world: &World,
+        camera: &Camera,
+    ) -> GameResult<Hud> {
+        let tile_size = REFERENCE_WIDTH / world.config.tiles_on_width;
+        let scale = tile_size / TILE_PIXELS;
+        let mut ui = Ui::new();
+
+        let letter_size = 1.5 * TILE_PIXELS * scale;
+        let bag = ui.add(
+            Anchor::TopLeft,
+            Vector2::new(MARGIN, MARGIN),
+            Vector2::new(0.0, 0.0),
+            Widget::Stack(StackPanel::new(
+                Direction::Vertical,
+                -BAG_OVERLAP * letter_size,
+                0.5,
+            )),
+        );
+        ui.add(
+            Anchor::TopLeft,
+            Vector2::new(2.0, 1.0) * tile_size,
+            Vector2::new(0.5, 0.5),
+            Widget::Sprite(Sprite::new(SpriteSource::Region(images.arrow), scale)),
+        );
+        let score = ui.add(
+            Anchor::TopRight,
+            Vector2::new(-100.0, 10.0),
+            Vector2::new(0.0, 0.0),
+            Widget::Label(Label::new(ctx, "Score: 0")?),
+        );
+        let ground = ui.add(
+            Anchor::TopRight,
+            Vector2::new(-100.0, tile_size),
+            Vector2::new(0.0, 0.5),
+            Widget::ProgressBar(ProgressBar::new(
+                images.progress_bar,
+                images.progress_bar_filled,
+                Vector2::new(scale * 0.75, scale / 2.0),
+            )),
+        );
+        let oldest = ui.add(
+            Anchor::TopRight,
+            Vector2::new(-100.0, 2.0 * tile_size),
+            Vector2::new(0.0, 0.5),
+            Widget::Stack(StackPanel::new(Direction::Horizontal, MARGIN, 0.5)),
+        );
+        let handover = ui.add(
+            Anchor::TopLeft,
+            Vector2::new(0.0, 0.0),
+            Vector2::new(0.5, 1.0),
+            Widget::ProgressBar(ProgressBar::new(
+                images.progress_bar,
+                images.progress_bar_filled,
+                Vector2::new(scale / 2.0, scale / 4.0),
+            )),
+        );
+        let house_pointer = ui.add(
+            Anchor::TopLeft,
+            Vector2::new(0.0, 0.0),
+            Vector2::new(0.5, 0.5),
+            Widget::Sprite(Sprite::new(SpriteSource::Region(images.pointer), scale)),
+        );
+        let house_distance = ui.add(
+            Anchor::TopLeft,
+            Vector2::new(0.0, 0.0),
+            Vector2::new(0.5, 0.5),
+            Widget::Label(Label::new(ctx, "")?),
+        );
+
+        let mut hud = Hud {
+            ui,
+            bag,
+            score,
+            ground,
+            oldest,
+            handover,
+            house_pointer,
+            house_distance,
+            letter_pointers: Vec::new(),
+            pointer: images.pointer,
+            scale,
+            shown_bag: Vec::new(),
+            shown_oldest: None,
+            shown_score: 0,
+            shown_distance: None,
+        };
+        hud.update(ctx, world, camera)?;
+        Ok(hud)
+    }
+
+    /// Bring the widgets up to date with the world, as it is seen through `camera`.
+    pub fn update(&mut self, ctx: &mut Context, world: &World, camera: &Camera) -> GameResult<()> {
+        let bag: Vec<(u32, LetterKind, bool)> = world
+            .holding_letters
+            .iter()
+            .map(|letter| (letter.number, letter.kind, letter.overdue(world.time)))
+            .collect();
+        if bag != self.shown_bag {
+            let scale = 1.5 * self.scale;
+            let stack = self.ui.get_mut(self.bag).stack_mut();
+            stack.clear();
+            for &(number, kind, overdue) in &bag {
+                let letter = letter_sprite(number, kind, overdue, scale);
+                stack.push(Widget::Sprite(letter));
+            }
+            self.shown_bag = bag;
+        }
+        let oldest = world.oldest_letter().map(|letter| {
+            let age = letter.age(world.time) as u32;
+            (letter.number, letter.kind, age, letter.overdue(world.time))
+        });
+        if oldest != self.shown_oldest {
+            if let Some((number, kind, age, overdue)) = oldest {
+                let letter = letter_sprite(number, kind, overdue, self.scale);
+                let mut label = Label::new(ctx, &format!("{} s", age))?;
+                if overdue {
+                    label.set_color(color(URGENT));
+                }
+                let stack = self.ui.get_mut(self.oldest).stack_mut();
+                stack.clear();
+                stack.push(Widget::Sprite(letter));
+                stack.push(Widget::Label(label));
+            }
+            self.ui.set_visible(self.oldest, oldest.is_some());
+            self.shown_oldest = oldest;
+        }
+        if world.score() != self.shown_score {
+            self.ui
+                .get_mut(self.score)
+                .label_mut()
+                .set_text(ctx, &format!("Score: {}", world.score()))?;
+            self.shown_score = world.score();
+        }
+        self.ui
+            .get_mut(self.ground)
+            .progress_bar_mut()
+            .set_value(world.ground_fill());
+        self.update_handover(ctx, world, camera);
+        self.update_pointers(ctx, world, camera)
+    }
+
+    /// Show how long is left to stand at the house to hand over a registered letter.
+    fn update_handover(&mut self, ctx: &Context, world: &World, camera: &Camera) {
+        let registered = world
+            .holding_letters
+            .front()
+            .map_or(false, |letter| letter.kind == LetterKind::Registered);
+        let house = match world.next_house() {
+            Some(house) if registered && world.handover_time > 0.0 => house,
+            _ => {
+                self.ui.set_visible(self.handover, false);
+                return;
+            }
+        };
+        let layout = Layout::new(ctx);
+        let above = house.position - Vector2::new(0.0, HOUSE_HALF_SIZE + 0.25);
+        let position = camera.world_to_screen(above).coords / layout.scale();
+        self.ui.set_offset(self.handover, position);
+        self.ui.set_visible(self.handover, true);
+        self.ui
+            .get_mut(self.handover)
+            .progress_bar_mut()
+            .set_value(world.handover_time / world.config.registered_time);
+    }
+
+    /// Point at the house for the front letter and at the letters on the ground, as far as they
+    /// are off the screen.
+    fn update_pointers(
+        &mut self,
+        ctx: &mut Context,
+        world: &World,
+        camera: &Camera,
+    ) -> GameResult<()> {
+        let layout = Layout::new(ctx);
+
+        let house_pointer = world.next_house().and_then(|house| {
+            pointer(camera, &layout, house.position, HOUSE_HALF_SIZE)
+                .map(|pointer| (pointer, house.position))
+        });
+        let distance =
+            house_pointer.map(|(_, position)| (position - world.player).norm().round() as u32);
+        if let Some(((position, rotation), _)) = house_pointer {
+            self.ui.set_offset(self.house_pointer, position);
+            self.ui
+                .get_mut(self.house_pointer)
+                .sprite_mut()
+                .set_rotation(rotation);
+            // The distance goes on the side of the pointer that faces the middle of the screen
+            let inwards = Vector2::new(rotation.cos(), rotation.sin());
+            self.ui
+                .set_offset(self.house_distance, position + inwards * DISTANCE_GAP);
+        }
+        if distance != self.shown_distance {
+            if let Some(distance) = distance {
+                self.ui
+                    .get_mut(self.house_distance)
+                    .label_mut()
+                    .set_text(ctx, &distance.to_string())?;
+            }
+            self.shown_distance = distance;
+        }
+        self.ui.set_visible(self.house_pointer, distance.is_some());
+        self.ui.set_visible(self.house_distance, distance.is_some());
+
+        let mut shown = 0;
+        for letter in &world.letters {
+            let (position, rotation) = match pointer(camera, &layout, letter.position, 0.5) {
+                Some(pointer) => pointer,
+                None => continue,
+            };
+            if shown == self.letter_pointers.len() {
+                let sprite = Sprite::new(
+                    SpriteSource::Region(self.pointer),
+                    LETTER_POINTER_SIZE * self.scale,
+                );
+                let id = self.ui.add(
+                    Anchor::TopLeft,
+                    position,
+                    Vector2::new(0.5, 0.5),
+                    Widget::Sprite(sprite),
+                );
+                self.letter_pointers.push(id);
+            }
+            let id = self.letter_pointers[shown];
+            self.ui.set_offset(id, position);
+            self.ui.set_visible(id, true);
+            let sprite = self.ui.get_mut(id).sprite_mut();
+            sprite.set_rotation(rotation);
+            sprite.set_color(Some(urgency_color(letter.lateness(world.time))));
+            shown += 1;
+        }
+        for &id in &self.letter_pointers[shown..] {
+            self.ui.set_visible(id, false);
+        }
+        Ok(())
+    }
+
+    pub fn draw(&mut self, ctx: &mut Context, images: &Images, time: f32) -> GameResult<()> {
+        self.ui.draw(ctx, images, time)
+    }
+}
+
+/// A letter as it is held in the bag, turned red when it is overdue.
+fn letter_sprite(number: u32, kind: LetterKind, overdue: bool, scale: f32) -> Sprite {
+    let clip = SpriteSource::Clip(animation::letter_clip_name(kind, true));
+    let mut letter = Sprite::new(clip, scale).with_number(number);
+    if overdue {
+        letter.set_color(Some(color(URGENT)));
+    }
+    letter
+}
+
+/// Where to point at something at `point` in the level, `half_size` tiles around, if it is off
+/// the screen: the place on the edge of the screen in the direction of the thing as seen from
+/// the middle, in reference pixels from the top left, and the rotation of the pointer.
+fn pointer(
+    camera: &Camera,
+    layout: &Layout,
+    point: Point2,
+    half_size: f32,
+) -> Option<(Vector2, f32)> {
+    if camera.is_visible(point, half_size) {
+        return None;
+    }
+    let middle = layout.size() / layout.scale() / 2.0;
+    let direction = camera.world_to_screen(point).coords / layout.scale() - middle;
+    // Move along the direction until the first edge is reached
+    let reach = (0..2)
+        .map(|axis| (middle[axis] - POINTER_MARGIN) / direction[axis].abs())
+        .fold(1.0, f32::min);
+    // The pointer image points left, so it is turned half a turn more than the direction
+    let rotation = direction[1].atan2(direction[0]) + PI;
+    Some((middle + direction * reach, rotation))
+}
+
+/// The colour of the pointers at letters that are `lateness` of the way to being due, going
+/// from calm to urgent.
+fn urgency_color(lateness: f32) -> Color {
+    let lateness = lateness.min(1.0);
+    let (from, to, amount) = if lateness < 0.5 {
+        (CALM, WORRYING, lateness * 2.0)
+    } else {
+        (WORRYING, URGENT, lateness * 2.0 - 1.0)
+    };
+    let mix = |channel: usize| from[channel] + (to[channel] - from[channel]) * amount;
+    color([mix(0), mix(1), mix(2)])
+}
+
+fn color(rgb: [f32; 3]) -> Color {
+    Color::new(rgb[0], rgb[1], rgb[2], 1.0)
+}
@@ -1,45 +1,157 @@
-use ggez::graphics::{FilterMode, Image};
+use ggez::graphics::spritebatch::SpriteBatch;
+use ggez::graphics::{DrawParam, Point2, Vector2};
 use ggez::*;
 
+use animation::{Animations, Frame};
+use atlas::{Atlas, AtlasBuilder, Region};
+
+/// The size of a digit in the digits image, in pixels.
+const DIGIT_WIDTH: u32 = 5;
+const DIGIT_HEIGHT: u32 = 7;
+/// The gap between the digits of a number, in pixels.
+const DIGIT_SPACING: u32 = 1;
+/// The position of the multiplication sign in the digits image, after the digits.
+const TIMES_SIGN: u32 = 10;
+
+/// Every sprite of the game, packed into a single atlas. The fields are the parts of the atlas
+/// with each image.
 pub struct Images {
-    pub grass: Image,
-    pub player_front: Image,
-    pub player_left: Image,
-    pub player_up: Image,
-    pub houses: Vec<Image>,
-    pub sign: Image,
-    pub letter: Image,
-    pub arrow: Image,
-    pub trees: Image,
-    pub progress_bar: Image,
-    pub progress_bar_filled: Image,
+    pub atlas: Atlas,
+    pub grass: Region,
+    pub road: Region,
+    pub water: Region,
+    pub fence: Region,
+    pub sign: Region,
+    pub arrow: Region,
+    /// A white arrow pointing left, tinted to point at things off the screen.
+    pub pointer: Region,
+    pub trees: Region,
+    pub progress_bar: Region,
+    pub progress_bar_filled: Region,
+    /// The digits from 0 to 9 and a multiplication sign, next to each other.
+    pub digits: Region,
+    /// The postman, letters and houses, which are drawn from sprite sheets.
+    pub animations: Animations,
 }
 
 impl Images {
     pub fn new(ctx: &mut Context) -> GameResult<Images> {
+        let mut builder = AtlasBuilder::new();
+        let grass = builder.add(ctx, "/grass.png")?;
+        let road = builder.add(ctx, "/road.png")?;
+        let water = builder.add(ctx, "/water.png")?;
+        let fence = builder.add(ctx, "/fence.png")?;
+        let sign = builder.add(ctx, "/sign.png")?;
+        let arrow = builder.add(ctx, "/arrow.png")?;
+        let pointer = builder.add(ctx, "/pointer.png")?;
+        let trees = builder.add(ctx, "/trees.png")?;
+        let progress_bar = builder.add(ctx, "/progress_bar.png")?;
+        let progress_bar_filled = builder.add(ctx, "/progress_bar_filled.png")?;
+        let digits = builder.add(ctx, "/digits.png")?;
+        let animations = Animations::load(ctx, &mut builder)?;
         Ok(Images {
-            grass: Images::load_image(ctx, "/grass.png")?,
-            player_front: Images::load_image(ctx, "/player_front.png")?,
-            player_left: Images::load_image(ctx, "/player_left.png")?,
-            player_up: Images::load_image(ctx, "/player_up.png")?,
-            houses: vec![
-                Images::load_image(ctx, "/house_1.png")?,
-                Images::load_image(ctx, "/house_2.png")?,
-                Images::load_image(ctx, "/house_3.png")?,
-                Images::load_image(ctx, "/house_4.png")?,
-            ],
-            sign: Images::load_image(ctx, "/sign.png")?,
-            letter: Images::load_image(ctx, "/letter.png")?,
-            arrow: Images::load_image(ctx, "/arrow.png")?,
-            trees: Images::load_image(ctx, "/trees.png")?,
-            progress_bar: Images::load_image(ctx, "/progress_bar.png")?,
-            progress_bar_filled: Images::load_image(ctx, "/progress_bar_filled.png")?,
+            atlas: builder.build(ctx)?,
+            grass,
+            road,
+            water,
+            fence,
+            sign,
+            arrow,
+            pointer,
+            trees,
+            progress_bar,
+            progress_bar_filled,
+            digits,
+            animations,
         })
     }
 
-    fn load_image(ctx: &mut Context, path: &str) -> GameResult<Image> {
-        let mut image = Image::new(ctx, path)?;
-        image.set_filter(FilterMode::Nearest);
-        Ok(image)
+    /// The number of different house sprites.
+    pub fn house_count(&self) -> usize {
+        self.animations.house_count()
+    }
+
+    /// An empty sprite batch that draws from the atlas.
+    pub fn batch(&self) -> SpriteBatch {
+        SpriteBatch::new(self.atlas.image.clone())
+    }
+
+    /// The parameters to draw a region of the atlas with. The source rectangle of `param` is a
+    /// fraction of the region, as if it were an image of its own.
+    fn sprite(&self, region: Region, param: DrawParam) -> DrawParam {
+        DrawParam {
+            src: self.atlas.src(region, param.src),
+            ..param
+        }
+    }
+
+    pub fn add(&self, batch: &mut SpriteBatch, region: Region, param: DrawParam) {
+        batch.add(self.sprite(region, param));
+    }
+
+    /// The parameters to draw an animation frame with, mirrored and moved as the clip says.
+    fn frame_param(frame: Frame, param: DrawParam) -> DrawParam {
+        let mut param = param;
+        if frame.mirror {
+            param.scale[0] *= -1.0;
+        }
+        param.dest += Vector2::new(
+            frame.offset[0] * param.scale[0],
+            frame.offset[1] * param.scale[1],
+        );
+        param
+    }
+
+    pub fn add_frame(&self, batch: &mut SpriteBatch, frame: Frame, param: DrawParam) {
+        self.add(batch, frame.region, Images::frame_param(frame, param));
+    }
+
+    /// Add the frame of the clip that shows after `time` seconds to the batch.
+    pub fn add_clip(&self, batch: &mut SpriteBatch, clip: &str, time: f32, param: DrawParam) {
+        self.add_frame(batch, self.animations.frame(clip, time), param);
     }
+
+    /// Add a number to the batch, centred on the destination of `param`.
+    pub fn add_number(&self, batch: &mut SpriteBatch, number: u32, param: DrawParam) {
+        self.add_glyphs(batch, &digits(number), param);
+    }
+
+    /// Add a multiplication sign followed by `factor` to the batch, centred on the destination
+    /// of `param`.
+    pub fn add_multiplier(&self, batch: &mut SpriteBatch, factor: u32, param: DrawParam) {
+        let mut glyphs = vec![TIMES_SIGN];
+        glyphs.extend(digits(factor));
+        self.add_glyphs(batch, &glyphs, param);
+    }
+
+    /// Add glyphs of the digits image to the batch, centred on the destination of `param`.
+    fn add_glyphs(&self, batch: &mut SpriteBatch, glyphs: &[u32], param: DrawParam) {
+        let advance = (DIGIT_WIDTH + DIGIT_SPACING) as f32;
+        let width = advance * glyphs.len() as f32 - DIGIT_SPACING as f32;
+        for (i, &glyph) in glyphs.iter().enumerate() {
+            let region = self
+                .digits
+                .part(glyph * DIGIT_WIDTH, 0, DIGIT_WIDTH, DIGIT_HEIGHT);
+            let left = advance * i as f32 - width / 2.0;
+            let top = -(DIGIT_HEIGHT as f32) / 2.0;
+            self.add(
+                batch,
+                region,
+                DrawParam {
+                    dest: param.dest + Vector2::new(left * param.scale[0], top * param.scale[1]),
+                    offset: Point2::origin(),
+                    ..param
+                },
+            );
+        }
+    }
+}
+
+/// The positions of the digits of a number in the digits image.
+fn digits(number: u32) -> Vec<u32> {
+    number
+        .to_string()
+        .bytes()
+        .map(|digit| (digit - b'0') as u32)
+        .collect()
 }
@@ -0,0 +1,354 @@
+use ggez::event::{Axis, Button, Keycode};
+use ggez::graphics::Vector2;
+use ggez::{Context, GameResult};
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{Read, Write};
+
+use toml;
+
+use game_state::InputEvent;
+
+/// Where the bindings are stored, in the user config directory.
+const PATH: &str = "/controls.toml";
+
+/// How far a stick has to be pushed before it counts, as a fraction of the full range.
+const STICK_DEAD_ZONE: f32 = 0.2;
+
+/// Something the player can do, independent of the key or button that does it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    Confirm,
+    Back,
+    Pause,
+    ZoomIn,
+    ZoomOut,
+    Fullscreen,
+    Minimap,
+    RotateBag,
+    SwapLetters,
+    DropLetter,
+}
+
+impl Action {
+    pub const ALL: [Action; 14] = [
+        Action::MoveUp,
+        Action::MoveDown,
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::Confirm,
+        Action::Back,
+        Action::Pause,
+        Action::ZoomIn,
+        Action::ZoomOut,
+        Action::Fullscreen,
+        Action::Minimap,
+        Action::RotateBag,
+        Action::SwapLetters,
+        Action::DropLetter,
+    ];
+
+    /// The name used in the bindings file.
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::MoveUp => "move_up",
+            Action::MoveDown => "move_down",
+            Action::MoveLeft => "move_left",
+            Action::MoveRight => "move_right",
+            Action::Confirm => "confirm",
+            Action::Back => "back",
+            Action::Pause => "pause",
+            Action::ZoomIn => "zoom_in",
+            Action::ZoomOut => "zoom_out",
+            Action::Fullscreen => "fullscreen",
+            Action::Minimap => "minimap",
+            Action::RotateBag => "rotate_bag",
+            Action::SwapLetters => "swap_letters",
+            Action::DropLetter => "drop_letter",
+        }
+    }
+
+    /// The name shown in the options menu.
+    pub fn label(self) -> &'static str {
+        match self {
+            Action::MoveUp => "Move up",
+            Action::MoveDown => "Move down",
+            Action::MoveLeft => "Move left",
+            Action::MoveRight => "Move right",
+            Action::Confirm => "Confirm",
+            Action::Back => "Back",
+            Action::Pause => "Pause",
+            Action::ZoomIn => "Zoom in",
+            Action::ZoomOut => "Zoom out",
+            Action::Fullscreen => "Fullscreen",
+            Action::Minimap => "Minimap",
+            Action::RotateBag => "Rotate bag",
+            Action::SwapLetters => "Swap letters",
+            Action::DropLetter => "Drop letter",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Action> {
+        Action::ALL
+            .iter()
+            .cloned()
+            .find(|action| action.name() == name)
+    }
+}
+
+/// The bindings file as it is stored, with every key and button by name.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+struct BindingsFile {
+    keys: BTreeMap<String, Vec<String>>,
+    buttons: BTreeMap<String, Vec<String>>,
+}
+
+/// Which keys and gamepad buttons trigger each action.
+#[derive(Clone, Debug)]
+pub struct Bindings {
+    keys: HashMap<Action, Vec<Keycode>>,
+    buttons: HashMap<Action, Vec<Button>>,
+}
+
+impl Default for Bindings {
+    fn default() -> Bindings {
+        let mut keys = HashMap::new();
+        keys.insert(Action::MoveUp, vec![Keycode::Up, Keycode::W]);
+        keys.insert(Action::MoveDown, vec![Keycode::Down, Keycode::S]);
+        keys.insert(Action::MoveLeft, vec![Keycode::Left, Keycode::A]);
+        keys.insert(Action::MoveRight, vec![Keycode::Right, Keycode::D]);
+        keys.insert(Action::Confirm, vec![Keycode::Space, Keycode::Return]);
+        keys.insert(Action::Back, vec![Keycode::Escape]);
+        keys.insert(Action::Pause, vec![Keycode::Escape, Keycode::P]);
+        keys.insert(Action::ZoomIn, vec![Keycode::Equals, Keycode::KpPlus]);
+        keys.insert(Action::ZoomOut, vec![Keycode::Minus, Keycode::KpMinus]);
+        keys.insert(Action::Fullscreen, vec![Keycode::F11]);
+        keys.insert(Action::Minimap, vec![Keycode::M]);
+        keys.insert(Action::RotateBag, vec![Keycode::Q]);
+        keys.insert(Action::SwapLetters, vec![Keycode::E]);
+        keys.insert(Action::DropLetter, vec![Keycode::X]);
+        let mut buttons = HashMap::new();
+        buttons.insert(Action::MoveUp, vec![Button::DPadUp]);
+        buttons.insert(Action::MoveDown, vec![Button::DPadDown]);
+        buttons.insert(Action::MoveLeft, vec![Button::DPadLeft]);
+        buttons.insert(Action::MoveRight, vec![Button::DPadRight]);
+        buttons.insert(Action::Confirm, vec![Button::A]);
+        buttons.insert(Action::Back, vec![Button::B]);
+        buttons.insert(Action::Pause, vec![Button::Start]);
+        buttons.insert(Action::ZoomIn, vec![Button::RightShoulder]);
+        buttons.insert(Action::ZoomOut, vec![Button::LeftShoulder]);
+        buttons.insert(Action::Minimap, vec![Button::Back]);
+        buttons.insert(Action::RotateBag, vec![Button::X]);
+        buttons.insert(Action::SwapLetters, vec![Button::Y]);
+        buttons.insert(Action::DropLetter, vec![Button::RightStick]);
+        Bindings { keys, buttons }
+    }
+}
+
+impl Bindings {
+    /// Load the bindings from the user config directory. Actions that are missing or can't be
+    /// read keep their default bindings.
+    pub fn load(ctx: &mut Context) -> Bindings {
+        let mut bindings = Bindings::default();
+        if !ctx.filesystem.exists(PATH) {
+            return bindings;
+        }
+        let mut source = String::new();
+        let read = ctx
+            .filesystem
+            .open(PATH)
+            .and_then(|mut file| Ok(file.read_to_string(&mut source)?));
+        if let Err(e) = read {
+            println!("Could not read the controls: {}", e);
+            return bindings;
+        }
+        let file: BindingsFile = match toml::from_str(&source) {
+            Ok(file) => file,
+            Err(e) => {
+                println!("Ignoring the controls in {}: {}", PATH, e);
+                return bindings;
+            }
+        };
+        for (name, keys) in &file.keys {
+            match (Action::from_name(name), parse_all(keys, Keycode::from_name)) {
+                (Some(action), Some(keys)) => {
+                    bindings.keys.insert(action, keys);
+                }
+                _ => println!("Ignoring the keys for \"{}\" in {}", name, PATH),
+            }
+        }
+        for (name, buttons) in &file.buttons {
+            match (
+                Action::from_name(name),
+                parse_all(buttons, Button::from_string),
+            ) {
+                (Some(action), Some(buttons)) => {
+                    bindings.buttons.insert(action, buttons);
+                }
+                _ => println!("Ignoring the buttons for \"{}\" in {}", name, PATH),
+            }
+        }
+        bindings
+    }
+
+    pub fn save(&self, ctx: &mut Context) -> GameResult<()> {
+        let mut file = BindingsFile::default();
+        for &action in Action::ALL.iter() {
+            let keys = self.keys_for(action).iter().map(|key| key.name()).collect();
+            file.keys.insert(action.name().to_string(), keys);
+            let buttons = self
+                .buttons_for(action)
+                .iter()
+                .map(|button| button.string())
+                .collect();
+            file.buttons.insert(action.name().to_string(), buttons);
+        }
+        // Serializing plain lists of strings can't fail
+        let source = toml::to_string(&file).expect("Could not write the controls");
+        let mut writer = ctx.filesystem.create(PATH)?;
+        writeln!(writer, "# The controls, as changed from the options menu.")?;
+        writer.write_all(source.as_bytes())?;
+        Ok(())
+    }
+
+    pub fn keys_for(&self, action: Action) -> &[Keycode] {
+        self.keys.get(&action).map(|keys| &keys[..]).unwrap_or(&[])
+    }
+
+    pub fn buttons_for(&self, action: Action) -> &[Button] {
+        self.buttons
+            .get(&action)
+            .map(|buttons| &buttons[..])
+            .unwrap_or(&[])
+    }
+
+    pub fn set_key(&mut self, action: Action, keycode: Keycode) {
+        self.keys.insert(action, vec![keycode]);
+    }
+
+    pub fn set_button(&mut self, action: Action, button: Button) {
+        self.buttons.insert(action, vec![button]);
+    }
+
+    /// All actions the event presses. Key repeats don't press anything.
+    fn pressed_by(&self, event: &InputEvent) -> Vec<Action> {
+        Action::ALL
+            .iter()
+            .cloned()
+            .filter(|&action| match *event {
+                InputEvent::KeyDown {
+                    keycode,
+                    repeat: false,
+                } => self.keys_for(action).contains(&keycode),
+                InputEvent::ButtonDown(button) => self.buttons_for(action).contains(&button),
+                _ => false,
+            })
+            .collect()
+    }
+
+    /// All actions the event releases.
+    fn released_by(&self, event: &InputEvent) -> Vec<Action> {
+        Action::ALL
+            .iter()
+            .cloned()
+            .filter(|&action| match *event {
+                InputEvent::KeyUp { keycode, .. } => self.keys_for(action).contains(&keycode),
+                InputEvent::ButtonUp(button) => self.buttons_for(action).contains(&button),
+                _ => false,
+            })
+            .collect()
+    }
+}
+
+fn parse_all<T, F: Fn(&str) -> Option<T>>(names: &[String], parse: F) -> Option<Vec<T>> {
+    names.iter().map(|name| parse(name)).collect()
+}
+
+/// The state of the controls: the bindings, and which actions are held down.
+pub struct Controls {
+    pub bindings: Bindings,
+    held: HashSet<Action>,
+    /// The position of the left stick, from -1 to 1 on both axes.
+    stick: Vector2,
+}
+
+impl Controls {
+    pub fn new(bindings: Bindings) -> Controls {
+        Controls {
+            bindings,
+            held: HashSet::new(),
+            stick: Vector2::new(0.0, 0.0),
+        }
+    }
+
+    /// Keep track of held actions and the stick. Every event goes through here before it is
+    /// passed to a state.
+    pub fn handle(&mut self, event: &InputEvent) {
+        for action in self.bindings.pressed_by(event) {
+            self.held.insert(action);
+        }
+        for action in self.bindings.released_by(event) {
+            self.held.remove(&action);
+        }
+        if let InputEvent::AxisMoved(axis, value) = *event {
+            match axis {
+                Axis::LeftX => self.stick[0] = value,
+                Axis::LeftY => self.stick[1] = value,
+                _ => {}
+            }
+        }
+    }
+
+    /// Whether the event presses the action.
+    pub fn pressed(&self, event: &InputEvent, action: Action) -> bool {
+        self.bindings.pressed_by(event).contains(&action)
+    }
+
+    pub fn is_held(&self, action: Action) -> bool {
+        self.held.contains(&action)
+    }
+
+    /// The direction to walk in. The stick is used when it is pushed, otherwise the movement
+    /// keys and buttons, which walk at full speed.
+    pub fn movement(&self) -> Vector2 {
+        if self.stick.norm() > STICK_DEAD_ZONE {
+            let stick = if self.stick.norm() > 1.0 {
+                self.stick.normalize()
+            } else {
+                self.stick
+            };
+            // Start from zero at the edge of the dead zone
+            return stick * ((stick.norm() - STICK_DEAD_ZONE) / (1.0 - STICK_DEAD_ZONE))
+                / stick.norm();
+        }
+        let axis = |negative, positive| {
+            let mut value = 0.0;
+            if self.is_held(negative) {
+                value -= 1.0;
+            }
+            if self.is_held(positive) {
+                value += 1.0;
+            }
+            value
+        };
+        let direction = Vector2::new(
+            axis(Action::MoveLeft, Action::MoveRight),
+            axis(Action::MoveUp, Action::MoveDown),
+        );
+        if direction.norm() > 1.0 {
+            direction.normalize()
+        } else {
+            direction
+        }
+    }
+}
+
+/// The full range of a stick axis as reported by the gamepad.
+pub fn axis_value(value: i16) -> f32 {
+    (value as f32 / 32767.0).max(-1.0)
+}
@@ -0,0 +1,87 @@
+use ggez::graphics::{self, DrawParam, Point2, Rect, Vector2};
+use ggez::{Context, GameResult};
+
+/// The size of the screen the interface is laid out for, in pixels. Other screens scale the
+/// interface to fit.
+pub const REFERENCE_WIDTH: f32 = 800.0;
+pub const REFERENCE_HEIGHT: f32 = 600.0;
+
+/// The point of the screen an element is placed relative to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Centre,
+    Bottom,
+    BottomRight,
+}
+
+impl Anchor {
+    /// Where the anchor is, as a fraction of the width and height of the screen.
+    fn fraction(self) -> Vector2 {
+        let (x, y) = match self {
+            Anchor::TopLeft => (0.0, 0.0),
+            Anchor::Top => (0.5, 0.0),
+            Anchor::TopRight => (1.0, 0.0),
+            Anchor::Centre => (0.5, 0.5),
+            Anchor::Bottom => (0.5, 1.0),
+            Anchor::BottomRight => (1.0, 1.0),
+        };
+        Vector2::new(x, y)
+    }
+}
+
+/// Make the screen coordinates match the pixels of the window, so nothing is stretched after
+/// the window is resized and high DPI screens are drawn at their full resolution.
+pub fn fit_screen(ctx: &mut Context) -> GameResult<()> {
+    let (width, height) = graphics::get_drawable_size(ctx);
+    graphics::set_screen_coordinates(ctx, Rect::new(0.0, 0.0, width as f32, height as f32))
+}
+
+/// Places the interface on the screen as it is now.
+///
+/// Positions are given in pixels of the reference screen, from an anchor. They are scaled by
+/// how much larger the screen is than the reference screen, in whichever direction it grew
+/// the least. The screen is measured in real pixels, so this scale includes the DPI.
+#[derive(Clone, Copy, Debug)]
+pub struct Layout {
+    size: Vector2,
+    scale: f32,
+}
+
+impl Layout {
+    pub fn new(ctx: &Context) -> Layout {
+        let screen = graphics::get_screen_coordinates(ctx);
+        Layout {
+            size: Vector2::new(screen.w, screen.h),
+            scale: (screen.w / REFERENCE_WIDTH).min(screen.h / REFERENCE_HEIGHT),
+        }
+    }
+
+    /// The size of the screen, in pixels.
+    pub fn size(&self) -> Vector2 {
+        self.size
+    }
+
+    /// How many pixels a pixel of the reference screen takes up.
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// The point `offset` reference pixels away from the anchor.
+    pub fn point(&self, anchor: Anchor, offset: Vector2) -> Point2 {
+        let fraction = anchor.fraction();
+        Point2::new(self.size[0] * fraction[0], self.size[1] * fraction[1]) + offset * self.scale
+    }
+
+    /// `param` moved to `offset` reference pixels away from the anchor, and scaled with the
+    /// screen.
+    pub fn place(&self, anchor: Anchor, offset: Vector2, param: DrawParam) -> DrawParam {
+        DrawParam {
+            dest: self.point(anchor, offset),
+            scale: param.scale * self.scale,
+            ..param
+        }
+    }
+}
@@ -0,0 +1,360 @@
+use ggez::{Context, GameError, GameResult};
+
+use std::collections::HashSet;
+use std::io::Read;
+
+use toml;
+
+use rand::Rng;
+
+use ggez::graphics::Point2;
+
+use config::{Config, Generator};
+use tilemap::{Terrain, TileMap};
+use town;
+use world::{self, Bounds, GameRng, HOUSE_HALF_SIZE, HOUSE_REACH, PLAYER_HALF_SIZE};
+
+/// Where the player starts on generated levels, unless the level is too small for it.
+const SPAWN: [f32; 2] = [10.0, 10.0];
+
+/// The random positions tried for a scattered house before giving up on placing the rest.
+const HOUSE_PLACEMENT_ATTEMPTS: u32 = 1000;
+
+/// A house placed by hand in a level file.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HouseSpec {
+    /// The centre of the house in tiles.
+    pub position: [f32; 2],
+    pub number: u32,
+    /// Which of the house images to use. A random one is picked when it is left out.
+    pub sprite: Option<usize>,
+}
+
+impl HouseSpec {
+    pub fn bounds(&self) -> Bounds {
+        Bounds::around(point(self.position), HOUSE_HALF_SIZE)
+    }
+}
+
+/// A rectangle the player can't walk through, such as a group of trees.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Obstacle {
+    /// The top left corner in tiles.
+    pub position: [f32; 2],
+    /// The width and height in tiles.
+    pub size: [f32; 2],
+}
+
+/// A map loaded from a level file in `resources/levels`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Level {
+    /// The width of the map in tiles.
+    pub width: u32,
+    /// The height of the map in tiles.
+    pub height: u32,
+    /// Where the player starts, in tiles.
+    pub spawn: [f32; 2],
+    pub houses: Vec<HouseSpec>,
+    #[serde(default)]
+    pub obstacles: Vec<Obstacle>,
+    /// The terrain, as one string of terrain symbols per row. The level is all grass when this
+    /// is left out.
+    #[serde(default)]
+    pub tiles: Vec<String>,
+}
+
+impl Level {
+    pub fn load(ctx: &mut Context, path: &str, resource_count: usize) -> GameResult<Level> {
+        let mut source = String::new();
+        ctx.filesystem.open(path)?.read_to_string(&mut source)?;
+        Level::parse(&source, resource_count).map_err(|e| match e {
+            GameError::ResourceLoadError(message) => {
+                GameError::ResourceLoadError(format!("Invalid level {}: {}", path, message))
+            }
+            e => e,
+        })
+    }
+
+    pub fn parse(source: &str, resource_count: usize) -> GameResult<Level> {
+        let level: Level = toml::from_str(source)
+            .map_err(|e| GameError::ResourceLoadError(e.to_string()))?;
+        level.validate(resource_count)?;
+        Ok(level)
+    }
+
+    /// Generate a level of the size in the config, with the generator picked in the config.
+    pub fn generate(config: &Config, rng: &mut GameRng, resource_count: usize) -> Level {
+        match config.generator {
+            Generator::Scatter => Level::scatter(config, rng, resource_count),
+            Generator::Town => town::generate(config, rng, resource_count),
+        }
+    }
+
+    /// Generate an open field with houses scattered randomly.
+    fn scatter(config: &Config, rng: &mut GameRng, resource_count: usize) -> Level {
+        let spawn_point = [
+            SPAWN[0].min(config.level_width as f32 / 2.0),
+            SPAWN[1].min(config.level_height as f32 / 2.0),
+        ];
+        // Keep some room around the spawn point, so the player doesn't start stuck to a house
+        let spawn = Bounds::around(point(spawn_point), PLAYER_HALF_SIZE + 1.0);
+        let mut houses: Vec<HouseSpec> = Vec::new();
+        // The config makes sure the houses fit, but if they don't the level has fewer houses
+        'houses: for house_number in 0..config.house_count {
+            for _ in 0..HOUSE_PLACEMENT_ATTEMPTS {
+                let position = [
+                    rng.gen_range(3.0, config.level_width as f32 - 3.0),
+                    rng.gen_range(3.0, config.level_height as f32 - 3.0),
+                ];
+                let new_house = HouseSpec {
+                    position,
+                    number: 1 + house_number,
+                    sprite: Some(rng.gen_range(0, resource_count)),
+                };
+                let overlaps_spawn = new_house.bounds().overlaps(&spawn);
+                if !overlaps_spawn
+                    && houses.iter().all(|house| {
+                        (house.position[0] - position[0]).abs() >= 4.0
+                            || (house.position[1] - position[1]).abs() >= 4.0
+                    }) {
+                    houses.push(new_house);
+                    continue 'houses;
+                }
+            }
+            break;
+        }
+        Level {
+            width: config.level_width,
+            height: config.level_height,
+            spawn: spawn_point,
+            houses,
+            obstacles: Vec::new(),
+            tiles: Vec::new(),
+        }
+    }
+
+    pub fn tile_map(&self) -> Result<TileMap, String> {
+        if self.tiles.is_empty() {
+            Ok(TileMap::new(self.width, self.height, Terrain::Grass))
+        } else {
+            TileMap::parse(self.width, self.height, &self.tiles)
+        }
+    }
+
+    /// Everything the player can't walk through.
+    pub fn solids(&self) -> Vec<Bounds> {
+        self.houses
+            .iter()
+            .map(HouseSpec::bounds)
+            .chain(self.obstacles.iter().map(Obstacle::bounds))
+            .collect()
+    }
+
+    /// The numbers of the houses the player can't deliver to, because there is no way to walk
+    /// to them from the spawn point. All houses count as unreachable when the tiles are invalid.
+    pub fn unreachable_houses(&self) -> Vec<u32> {
+        let reachable = match self.tile_map() {
+            Ok(tile_map) => tile_map.reachable_from(point(self.spawn), &self.solids()),
+            Err(_) => return self.houses.iter().map(|house| house.number).collect(),
+        };
+        self.houses
+            .iter()
+            .filter(|house| !reachable.can_reach(point(house.position), HOUSE_REACH))
+            .map(|house| house.number)
+            .collect()
+    }
+
+    fn contains(&self, point: [f32; 2]) -> bool {
+        point.iter().all(|coord| coord.is_finite())
+            && point[0] >= 0.0
+            && point[1] >= 0.0
+            && point[0] <= self.width as f32 - 1.0
+            && point[1] <= self.height as f32 - 1.0
+    }
+
+    fn validate(&self, resource_count: usize) -> GameResult<()> {
+        let error = |message: String| Err(GameError::ResourceLoadError(message));
+        if self.width < 12 || self.height < 8 {
+            return error(format!(
+                "the level is {}x{} tiles, but should be at least 12x8",
+                self.width, self.height
+            ));
+        }
+        if !self.contains(self.spawn) {
+            return error(format!(
+                "the spawn point {:?} is outside the level",
+                self.spawn
+            ));
+        }
+        let tile_map = match self.tile_map() {
+            Ok(tile_map) => tile_map,
+            Err(message) => return error(message),
+        };
+        let spawn = Bounds::around(point(self.spawn), PLAYER_HALF_SIZE);
+        if !tile_map.blocking_tiles(&spawn).is_empty() {
+            return error(format!(
+                "the spawn point {:?} is on terrain that can't be walked on",
+                self.spawn
+            ));
+        }
+        if self.solids().iter().any(|solid| solid.overlaps(&spawn)) {
+            return error(format!(
+                "the spawn point {:?} is inside a house or obstacle",
+                self.spawn
+            ));
+        }
+        if self.houses.is_empty() {
+            return error("there should be at least one house".to_string());
+        }
+        let mut numbers = HashSet::new();
+        for house in &self.houses {
+            if !self.contains(house.position) {
+                return error(format!(
+                    "house {} at {:?} is outside the level",
+                    house.number, house.position
+                ));
+            }
+            if !numbers.insert(house.number) {
+                return error(format!("there are multiple houses numbered {}", house.number));
+            }
+            if let Some(sprite) = house.sprite {
+                if sprite >= resource_count {
+                    return error(format!(
+                        "house {} uses sprite {}, but there are only {} house sprites",
+                        house.number, sprite, resource_count
+                    ));
+                }
+            }
+        }
+        for obstacle in &self.obstacles {
+            let mut coords = obstacle.position.iter().chain(&obstacle.size);
+            if coords.any(|coord| !coord.is_finite()) {
+                return error(format!(
+                    "the obstacle at {:?} with size {:?} should only have finite numbers",
+                    obstacle.position, obstacle.size
+                ));
+            }
+            let far_corner = [
+                obstacle.position[0] + obstacle.size[0],
+                obstacle.position[1] + obstacle.size[1],
+            ];
+            if obstacle.size[0] <= 0.0
+                || obstacle.size[1] <= 0.0
+                || obstacle.position[0] < 0.0
+                || obstacle.position[1] < 0.0
+                || far_corner[0] > self.width as f32
+                || far_corner[1] > self.height as f32
+            {
+                return error(format!(
+                    "the obstacle at {:?} with size {:?} doesn't fit in the level",
+                    obstacle.position, obstacle.size
+                ));
+            }
+        }
+        let unreachable = self.unreachable_houses();
+        if !unreachable.is_empty() {
+            return error(format!(
+                "houses {:?} can't be reached from the spawn point",
+                unreachable
+            ));
+        }
+        let reachable = tile_map.reachable_from(point(self.spawn), &self.solids());
+        if world::letter_tiles(&tile_map, &reachable, &self.solids()).is_empty() {
+            return error(
+                "there is no tile the player can reach that letters can appear on".to_string(),
+            );
+        }
+        Ok(())
+    }
+}
+
+fn point(coords: [f32; 2]) -> Point2 {
+    Point2::new(coords[0], coords[1])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The number of house images the levels are loaded with.
+    const HOUSE_IMAGES: usize = 4;
+
+    /// A small valid level, followed by `extra` lines.
+    fn source(extra: &str) -> String {
+        format!(
+            "width = 12\nheight = 8\nspawn = [2.0, 2.0]\n{}\n\
+             [[houses]]\nposition = [8.0, 4.0]\nnumber = 1\n",
+            extra
+        )
+    }
+
+    /// Check `level` is rejected, and return why.
+    fn rejection(level: &Level) -> String {
+        match level.validate(HOUSE_IMAGES) {
+            Err(GameError::ResourceLoadError(message)) => message,
+            Err(e) => panic!("failed with {:?}", e),
+            Ok(_) => panic!("{:?} was accepted", level),
+        }
+    }
+
+    #[test]
+    fn bundled_level_loads() {
+        let source = include_str!("../resources/levels/village.toml");
+        let level = Level::parse(source, HOUSE_IMAGES).unwrap();
+        assert_eq!((level.width, level.height), (30, 20));
+        assert!(!level.houses.is_empty());
+    }
+
+    #[test]
+    fn invalid_levels_are_rejected() {
+        let level = Level::parse(&source(""), HOUSE_IMAGES).unwrap();
+
+        let mut outside = level.clone();
+        outside.spawn = [12.0, 2.0];
+        assert_eq!(
+            rejection(&outside),
+            "the spawn point [12.0, 2.0] is outside the level"
+        );
+
+        let mut doubled = level.clone();
+        doubled.houses.push(HouseSpec {
+            position: [3.0, 6.0],
+            number: 1,
+            sprite: None,
+        });
+        assert_eq!(rejection(&doubled), "there are multiple houses numbered 1");
+
+        let mut sprite = level.clone();
+        sprite.houses[0].sprite = Some(HOUSE_IMAGES);
+        assert_eq!(
+            rejection(&sprite),
+            "house 1 uses sprite 4, but there are only 4 house sprites"
+        );
+
+        let mut obstacle = level.clone();
+        obstacle.obstacles.push(Obstacle {
+            position: [10.0, 0.0],
+            size: [3.0, 1.0],
+        });
+        assert!(rejection(&obstacle).contains("doesn't fit in the level"));
+
+        let mut not_finite = level.clone();
+        not_finite.obstacles.push(Obstacle {
+            position: [1.0, 6.0],
+            size: [std::f32::NAN, 1.0],
+        });
+        assert!(rejection(&not_finite).contains("should only have finite numbers"));
+        not_finite.obstacles.clear();
+        not_finite.houses[0].position[1] = std::f32::INFINITY;
+        assert!(rejection(&not_finite).contains("is outside the level"));
+    }
+
+    #[test]
+    fn malformed_levels_are_rejected() {
+        assert!(Level::parse(&source("rivers = 2"), HOUSE_IMAGES).is_err());
+        assert!(Level::parse("width = 12\nheight = 8\n", HOUSE_IMAGES).is_err());
+    }
+}
@@ -1,27 +1,114 @@
 extern crate ggez;
+extern crate image;
 extern crate rand;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate toml;
 use ggez::*;
 
+use std::env;
+use std::fs::File;
+
+mod animation;
+mod atlas;
+mod camera;
+mod config;
 mod game;
+mod game_over;
 mod game_state;
+mod highscores;
+mod hud;
 mod images;
+mod input;
+mod layout;
+mod level;
+mod menu;
+mod minimap;
+mod replay;
+mod scoring;
+mod screens;
 mod splash_screen;
+mod tilemap;
+mod town;
+mod ui;
+mod window;
+mod world;
 
+use config::Config;
+use game::GameOptions;
 use game_state::StateHolder;
 use images::Images;
+use level::Level;
+use replay::Replay;
+
+/// The options that can be given on the command line.
+struct Options {
+    /// Play with this seed instead of a random one, to reproduce a run.
+    seed: Option<u64>,
+    /// Play back a recorded game instead of playing.
+    replay: Option<Replay>,
+    /// The level file to play, relative to the resources directory.
+    level: Option<String>,
+}
+
+impl Options {
+    fn from_args() -> Options {
+        let mut options = Options {
+            seed: None,
+            replay: None,
+            level: None,
+        };
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--seed" => {
+                    let seed = args.next().expect("Expected a seed after --seed");
+                    options.seed = Some(seed.parse().expect("The seed should be a number"));
+                }
+                "--replay" => {
+                    let path = args.next().expect("Expected a file after --replay");
+                    let file = File::open(path).expect("Could not open replay");
+                    options.replay = Some(Replay::read(file).expect("Could not read replay"));
+                }
+                "--level" => {
+                    options.level = Some(args.next().expect("Expected a file after --level"));
+                }
+                _ => panic!("Unknown argument: {}", arg),
+            }
+        }
+        options
+    }
+}
 
 fn main() {
+    let options = Options::from_args();
     let cb = ContextBuilder::new("Too Much Post, Out Of Space", "Thomas den Hollander")
         .window_setup(
             conf::WindowSetup::default()
                 .title("Too Much Post, Out Of Space")
-                .resizable(false)
+                .resizable(true)
                 .samples(1)
                 .unwrap(),
         )
-        .window_mode(conf::WindowMode::default().dimensions(800, 600));
+        .window_mode(
+            conf::WindowMode::default()
+                .dimensions(800, 600)
+                .min_dimensions(window::MIN_WIDTH, window::MIN_HEIGHT),
+        );
     let ctx = &mut cb.build().unwrap();
     let images = Images::new(ctx).expect("Could not load images");
-    let mut game_state = StateHolder::startup(ctx, &images).unwrap();
+    let config = Config::load(ctx, "/config.toml").expect("Could not load config");
+    let level = options.level.as_ref().map(|path| {
+        Level::load(ctx, path, images.house_count()).expect("Could not load level")
+    });
+    let game_options = GameOptions {
+        config,
+        level,
+        level_path: options.level,
+        seed: options.seed,
+    };
+    let mut game_state =
+        StateHolder::startup(ctx, &images, game_options, options.replay).unwrap();
     event::run(ctx, &mut game_state).unwrap();
 }
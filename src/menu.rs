@@ -0,0 +1,398 @@
+use ggez::event::Keycode;
+use ggez::graphics::{DrawMode, DrawParam, Drawable, Point2, Rect, Text, Vector2};
+use ggez::*;
+
+use game::Game;
+use game_state::{InputEvent, Shared, State, Transition};
+use images::Images;
+use input::{Action, Bindings, Controls};
+use layout::{Anchor, Layout};
+use screens::{CreditsScreen, HighScoreScreen};
+use ui::{self, Direction, Label, StackPanel, Ui, Widget, WidgetId};
+
+/// Darken the whole screen, so an overlay stands out from the state below it.
+pub fn dim_screen(ctx: &mut Context) -> GameResult<()> {
+    let screen = graphics::get_screen_coordinates(ctx);
+    graphics::set_color(ctx, graphics::Color::new(0.0, 0.0, 0.0, 0.6))?;
+    graphics::rectangle(ctx, DrawMode::Fill, Rect::new(0.0, 0.0, screen.w, screen.h))?;
+    graphics::set_color(ctx, graphics::Color::new(1.0, 1.0, 1.0, 1.0))
+}
+
+/// A title with a vertical list of items, one of which is selected.
+pub struct Menu {
+    ui: Ui,
+    items: WidgetId,
+    item_count: usize,
+    selected: usize,
+}
+
+impl Menu {
+    pub fn new(ctx: &mut Context, title: &str, items: &[&str]) -> GameResult<Menu> {
+        let mut ui = Ui::new();
+        let title = Label::new(ctx, title)?.with_scale(2.0);
+        ui.add(
+            Anchor::Top,
+            Vector2::new(0.0, 150.0),
+            Vector2::new(0.5, 0.5),
+            Widget::Label(title),
+        );
+        let mut labels = Vec::new();
+        for item in items {
+            labels.push(Label::new(ctx, item)?);
+        }
+        // Long menus are packed closer together to fit on the screen
+        let spacing = (300.0 / items.len() as f32).min(40.0);
+        let line_height = labels.first().map_or(0.0, |label| label.size()[1]);
+        let mut stack = StackPanel::new(Direction::Vertical, spacing - line_height, 0.5);
+        for label in labels {
+            stack.push(Widget::Label(label));
+        }
+        let items_id = ui.add(
+            Anchor::Top,
+            Vector2::new(0.0, 250.0 - line_height / 2.0),
+            Vector2::new(0.5, 0.0),
+            Widget::Stack(stack),
+        );
+
+        let mut menu = Menu {
+            ui,
+            items: items_id,
+            item_count: items.len(),
+            selected: 0,
+        };
+        menu.highlight();
+        Ok(menu)
+    }
+
+    /// Change the label of an item, for items that show a setting.
+    pub fn set_item(&mut self, ctx: &mut Context, index: usize, label: &str) -> GameResult<()> {
+        self.ui
+            .get_mut(self.items)
+            .stack_mut()
+            .child_mut(index)
+            .label_mut()
+            .set_text(ctx, label)
+    }
+
+    /// Colour the selected item.
+    fn highlight(&mut self) {
+        let stack = self.ui.get_mut(self.items).stack_mut();
+        for i in 0..self.item_count {
+            let color = if i == self.selected {
+                graphics::Color::new(1.0, 1.0, 0.0, 1.0)
+            } else {
+                graphics::Color::new(1.0, 1.0, 1.0, 1.0)
+            };
+            stack.child_mut(i).label_mut().set_color(color);
+        }
+    }
+
+    /// Move the selection with the movement controls. Returns the selected item when it is
+    /// chosen.
+    pub fn input(&mut self, event: &InputEvent, controls: &Controls) -> Option<usize> {
+        if controls.pressed(event, Action::MoveUp) {
+            self.selected = (self.selected + self.item_count - 1) % self.item_count;
+        } else if controls.pressed(event, Action::MoveDown) {
+            self.selected = (self.selected + 1) % self.item_count;
+        } else if controls.pressed(event, Action::Confirm) {
+            return Some(self.selected);
+        }
+        self.highlight();
+        None
+    }
+
+    pub fn draw(&mut self, ctx: &mut Context, images: &Images) -> GameResult<()> {
+        self.ui.draw(ctx, images, 0.0)
+    }
+}
+
+pub struct MainMenu {
+    menu: Menu,
+}
+
+impl MainMenu {
+    pub fn new(ctx: &mut Context) -> GameResult<MainMenu> {
+        Ok(MainMenu {
+            menu: Menu::new(
+                ctx,
+                "Too Much Post, Out Of Space",
+                &["Play", "Options", "High scores", "Credits", "Quit"],
+            )?,
+        })
+    }
+}
+
+impl<'a> State<'a> for MainMenu {
+    fn draw(&mut self, ctx: &mut Context, shared: &Shared<'a>) -> GameResult<()> {
+        self.menu.draw(ctx, shared.images)
+    }
+
+    fn input(
+        &mut self,
+        ctx: &mut Context,
+        shared: &mut Shared<'a>,
+        event: &InputEvent,
+    ) -> GameResult<Transition<'a>> {
+        Ok(match self.menu.input(event, &shared.controls) {
+            Some(0) => {
+                let game = Game::new(ctx, shared.images, shared.options.clone(), None)?;
+                Transition::Push(Box::new(game))
+            }
+            Some(1) => Transition::Push(Box::new(OptionsMenu::new(ctx, shared)?)),
+            Some(2) => Transition::Push(Box::new(HighScoreScreen::new(ctx, shared)?)),
+            Some(3) => Transition::Push(Box::new(CreditsScreen::new(ctx)?)),
+            Some(_) => Transition::Quit,
+            None => Transition::None,
+        })
+    }
+}
+
+pub struct OptionsMenu {
+    menu: Menu,
+    /// Whether the label says fullscreen is on, which can also be changed with its key.
+    fullscreen_label: bool,
+}
+
+impl OptionsMenu {
+    pub fn new(ctx: &mut Context, shared: &Shared) -> GameResult<OptionsMenu> {
+        let mut options = OptionsMenu {
+            menu: Menu::new(ctx, "Options", &["", "", "", "", "Controls", "Back"])?,
+            fullscreen_label: shared.window.fullscreen,
+        };
+        options.update_labels(ctx, shared)?;
+        Ok(options)
+    }
+
+    fn update_labels(&mut self, ctx: &mut Context, shared: &Shared) -> GameResult<()> {
+        let on_off = |on| if on { "on" } else { "off" };
+        self.menu
+            .set_item(ctx, 0, &format!("Music: {}", on_off(shared.settings.music)))?;
+        self.menu.set_item(
+            ctx,
+            1,
+            &format!("Sounds: {}", on_off(shared.settings.sounds)),
+        )?;
+        self.menu.set_item(
+            ctx,
+            2,
+            &format!("Fullscreen: {}", on_off(shared.window.fullscreen)),
+        )?;
+        self.menu.set_item(
+            ctx,
+            3,
+            &format!("Minimap: {}", on_off(shared.settings.minimap)),
+        )?;
+        self.fullscreen_label = shared.window.fullscreen;
+        Ok(())
+    }
+}
+
+impl<'a> State<'a> for OptionsMenu {
+    fn update(
+        &mut self,
+        ctx: &mut Context,
+        shared: &mut Shared<'a>,
+    ) -> GameResult<Transition<'a>> {
+        if self.fullscreen_label != shared.window.fullscreen {
+            self.update_labels(ctx, shared)?;
+        }
+        Ok(Transition::None)
+    }
+
+    fn draw(&mut self, ctx: &mut Context, shared: &Shared<'a>) -> GameResult<()> {
+        self.menu.draw(ctx, shared.images)
+    }
+
+    fn input(
+        &mut self,
+        ctx: &mut Context,
+        shared: &mut Shared<'a>,
+        event: &InputEvent,
+    ) -> GameResult<Transition<'a>> {
+        if shared.controls.pressed(event, Action::Back) {
+            return Ok(Transition::Pop);
+        }
+        match self.menu.input(event, &shared.controls) {
+            Some(0) => shared.settings.music = !shared.settings.music,
+            Some(1) => shared.settings.sounds = !shared.settings.sounds,
+            Some(2) => {
+                shared.window.toggle_fullscreen(ctx)?;
+                if let Err(e) = shared.window.save(ctx) {
+                    println!("Could not save the window settings: {}", e);
+                }
+            }
+            Some(3) => shared.settings.minimap = !shared.settings.minimap,
+            Some(4) => return Ok(Transition::Push(Box::new(ControlsMenu::new(ctx, shared)?))),
+            Some(_) => return Ok(Transition::Pop),
+            None => return Ok(Transition::None),
+        }
+        self.update_labels(ctx, shared)?;
+        Ok(Transition::None)
+    }
+}
+
+/// The menu shown on top of a paused game. The game below isn't updated while it is open, so
+/// its clock stands still.
+pub struct PauseMenu {
+    menu: Menu,
+}
+
+impl PauseMenu {
+    pub fn new(ctx: &mut Context) -> GameResult<PauseMenu> {
+        Ok(PauseMenu {
+            menu: Menu::new(
+                ctx,
+                "Paused",
+                &["Resume", "Restart", "Options", "Quit to menu"],
+            )?,
+        })
+    }
+}
+
+impl<'a> State<'a> for PauseMenu {
+    fn is_overlay(&self) -> bool {
+        true
+    }
+
+    fn draw(&mut self, ctx: &mut Context, shared: &Shared<'a>) -> GameResult<()> {
+        dim_screen(ctx)?;
+        self.menu.draw(ctx, shared.images)
+    }
+
+    fn input(
+        &mut self,
+        ctx: &mut Context,
+        shared: &mut Shared<'a>,
+        event: &InputEvent,
+    ) -> GameResult<Transition<'a>> {
+        if shared.controls.pressed(event, Action::Pause)
+            || shared.controls.pressed(event, Action::Back)
+        {
+            return Ok(Transition::Pop);
+        }
+        Ok(match self.menu.input(event, &shared.controls) {
+            Some(0) => Transition::Pop,
+            Some(1) => {
+                // Nothing below the game is ever returned to, so it can start over on its own
+                let game = Game::new(ctx, shared.images, shared.options.clone(), None)?;
+                Transition::Clear(Box::new(game))
+            }
+            Some(2) => Transition::Push(Box::new(OptionsMenu::new(ctx, shared)?)),
+            Some(_) => Transition::Clear(Box::new(MainMenu::new(ctx)?)),
+            None => Transition::None,
+        })
+    }
+}
+
+/// Lists the bindings of every action. Choosing an action waits for the next key or gamepad
+/// button, which then replaces its bindings of that kind.
+pub struct ControlsMenu {
+    menu: Menu,
+    /// The action waiting for a new key or button.
+    rebinding: Option<Action>,
+    waiting_text: Text,
+}
+
+impl ControlsMenu {
+    pub fn new(ctx: &mut Context, shared: &Shared) -> GameResult<ControlsMenu> {
+        let mut items = vec![""; Action::ALL.len()];
+        items.push("Reset to defaults");
+        items.push("Back");
+        let mut controls = ControlsMenu {
+            menu: Menu::new(ctx, "Controls", &items)?,
+            rebinding: None,
+            waiting_text: ui::text(ctx, "Press a key or button, or Escape to cancel.")?,
+        };
+        controls.update_labels(ctx, shared)?;
+        Ok(controls)
+    }
+
+    fn update_labels(&mut self, ctx: &mut Context, shared: &Shared) -> GameResult<()> {
+        let bindings = &shared.controls.bindings;
+        for (i, &action) in Action::ALL.iter().enumerate() {
+            let names: Vec<String> = bindings
+                .keys_for(action)
+                .iter()
+                .map(|key| key.name())
+                .chain(bindings.buttons_for(action).iter().map(|b| b.string()))
+                .collect();
+            let label = format!("{}: {}", action.label(), names.join(", "));
+            self.menu.set_item(ctx, i, &label)?;
+        }
+        Ok(())
+    }
+
+    /// Save the bindings after a change. They keep working for this session if that fails.
+    fn save(&mut self, ctx: &mut Context, shared: &Shared) -> GameResult<()> {
+        if let Err(e) = shared.controls.bindings.save(ctx) {
+            println!("Could not save the controls: {}", e);
+        }
+        self.update_labels(ctx, shared)
+    }
+}
+
+impl<'a> State<'a> for ControlsMenu {
+    /// While waiting for a new binding, the fullscreen key can be bound like any other.
+    fn captures_input(&self) -> bool {
+        self.rebinding.is_some()
+    }
+
+    fn draw(&mut self, ctx: &mut Context, shared: &Shared<'a>) -> GameResult<()> {
+        self.menu.draw(ctx, shared.images)?;
+        if self.rebinding.is_some() {
+            let layout = Layout::new(ctx);
+            self.waiting_text.draw_ex(
+                ctx,
+                layout.place(
+                    Anchor::Bottom,
+                    Vector2::new(0.0, -50.0),
+                    DrawParam {
+                        offset: Point2::new(0.5, 0.5),
+                        ..Default::default()
+                    },
+                ),
+            )?;
+        }
+        Ok(())
+    }
+
+    fn input(
+        &mut self,
+        ctx: &mut Context,
+        shared: &mut Shared<'a>,
+        event: &InputEvent,
+    ) -> GameResult<Transition<'a>> {
+        if let Some(action) = self.rebinding {
+            match *event {
+                InputEvent::KeyDown {
+                    keycode: Keycode::Escape,
+                    ..
+                } => {}
+                InputEvent::KeyDown {
+                    keycode,
+                    repeat: false,
+                } => shared.controls.bindings.set_key(action, keycode),
+                InputEvent::ButtonDown(button) => {
+                    shared.controls.bindings.set_button(action, button)
+                }
+                _ => return Ok(Transition::None),
+            }
+            self.rebinding = None;
+            self.save(ctx, shared)?;
+            return Ok(Transition::None);
+        }
+        if shared.controls.pressed(event, Action::Back) {
+            return Ok(Transition::Pop);
+        }
+        match self.menu.input(event, &shared.controls) {
+            Some(i) if i < Action::ALL.len() => self.rebinding = Some(Action::ALL[i]),
+            Some(i) if i == Action::ALL.len() => {
+                shared.controls.bindings = Bindings::default();
+                self.save(ctx, shared)?;
+            }
+            Some(_) => return Ok(Transition::Pop),
+            None => {}
+        }
+        Ok(Transition::None)
+    }
+}
@@ -0,0 +1,179 @@
+use ggez::conf::NumSamples;
+use ggez::graphics::spritebatch::SpriteBatch;
+use ggez::graphics::{self, Canvas, Color, DrawMode, DrawParam, FilterMode, Point2, Rect, Vector2};
+use ggez::{Context, GameResult};
+
+use images::Images;
+use layout::{Anchor, Layout};
+use world::{World, HOUSE_HALF_SIZE};
+
+/// The size of a tile on the minimap canvas, in pixels.
+const PIXELS_PER_TILE: f32 = 6.0;
+/// The width of the frame around the level, in pixels of the canvas.
+const FRAME: f32 = 1.0;
+/// The largest the minimap is shown on the screen, in reference pixels.
+const MAX_SIZE: f32 = 200.0;
+/// The space between the minimap and the corner of the screen, in reference pixels.
+const MARGIN: f32 = 4.0;
+/// The size of the postman on the minimap, in tiles.
+const PLAYER_SIZE: f32 = 1.0;
+/// How much the game shows through the minimap.
+const TRANSPARENCY: f32 = 0.2;
+
+// The frame and the ground cover the whole canvas, so what was drawn on it before never
+// shows through
+const FRAME_COLOR: [f32; 4] = [0.1, 0.1, 0.1, 1.0];
+const GROUND_COLOR: [f32; 4] = [0.25, 0.45, 0.2, 1.0];
+const HOUSE_COLOR: [f32; 4] = [0.55, 0.35, 0.2, 1.0];
+const HOUSE_NUMBER_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+const LETTER_COLOR: [f32; 4] = [1.0, 0.9, 0.3, 1.0];
+const PLAYER_COLOR: [f32; 4] = [0.9, 0.2, 0.2, 1.0];
+
+/// What the minimap shows that can change during a game, in pixels of the canvas, to tell when
+/// the canvas has to be drawn again.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Shown {
+    player: [i32; 2],
+    /// The number and position of every letter on the ground.
+    letters: Vec<(u32, [i32; 2])>,
+}
+
+/// A map of the whole level in a corner of the screen, with the houses, the letters on the
+/// ground and the postman.
+///
+/// The map is drawn to a canvas of its own, which is only drawn again when something on it
+/// moved far enough to show.
+pub struct Minimap {
+    canvas: Canvas,
+    /// The digits of the numbers, drawn over the shapes.
+    batch: SpriteBatch,
+    shown: Option<Shown>,
+}
+
+impl Minimap {
+    pub fn new(ctx: &mut Context, images: &Images, world: &World) -> GameResult<Minimap> {
+        let size = level_size(world);
+        let mut canvas = Canvas::new(
+            ctx,
+            (size[0] + 2.0 * FRAME) as u32,
+            (size[1] + 2.0 * FRAME) as u32,
+            NumSamples::One,
+        )?;
+        canvas.set_filter(FilterMode::Nearest);
+        Ok(Minimap {
+            canvas,
+            batch: images.batch(),
+            shown: None,
+        })
+    }
+
+    /// Draw the canvas again if the world changed since it was last drawn.
+    pub fn update(&mut self, ctx: &mut Context, images: &Images, world: &World) -> GameResult<()> {
+        let shown = Shown {
+            player: pixel(world.player),
+            letters: world
+                .letters
+                .iter()
+                .map(|letter| (letter.number, pixel(letter.position)))
+                .collect(),
+        };
+        if self.shown.as_ref() == Some(&shown) {
+            return Ok(());
+        }
+
+        let screen = graphics::get_screen_coordinates(ctx);
+        let width = self.canvas.get_image().width() as f32;
+        let height = self.canvas.get_image().height() as f32;
+        graphics::set_canvas(ctx, Some(&self.canvas));
+        graphics::set_screen_coordinates(ctx, Rect::new(0.0, 0.0, width, height))?;
+        let drawn = self.draw_map(ctx, images, world);
+        // The screen is restored even if drawing failed, so the rest of the game still shows
+        graphics::set_canvas(ctx, None);
+        graphics::set_screen_coordinates(ctx, screen)?;
+        drawn?;
+
+        self.shown = Some(shown);
+        Ok(())
+    }
+
+    /// Draw the level on the canvas, which is the current target.
+    fn draw_map(&mut self, ctx: &mut Context, images: &Images, world: &World) -> GameResult<()> {
+        let size = level_size(world);
+        let frame = Rect::new(0.0, 0.0, size[0] + 2.0 * FRAME, size[1] + 2.0 * FRAME);
+        fill(ctx, FRAME_COLOR, frame)?;
+        fill(ctx, GROUND_COLOR, Rect::new(FRAME, FRAME, size[0], size[1]))?;
+
+        self.batch.clear();
+        let digits = |color: [f32; 4], position: Point2| DrawParam {
+            dest: canvas_position(position),
+            color: Some(Color::new(color[0], color[1], color[2], color[3])),
+            ..Default::default()
+        };
+        for house in &world.houses {
+            let bounds = square(house.position, 2.0 * HOUSE_HALF_SIZE);
+            fill(ctx, HOUSE_COLOR, bounds)?;
+            let param = digits(HOUSE_NUMBER_COLOR, house.position);
+            images.add_number(&mut self.batch, house.number, param);
+        }
+        for letter in &world.letters {
+            let param = digits(LETTER_COLOR, letter.position);
+            images.add_number(&mut self.batch, letter.number, param);
+        }
+        graphics::draw_ex(ctx, &self.batch, DrawParam::default())?;
+
+        fill(ctx, PLAYER_COLOR, square(world.player, PLAYER_SIZE))?;
+        graphics::set_color(ctx, Color::new(1.0, 1.0, 1.0, 1.0))
+    }
+
+    /// Draw the minimap in the bottom right corner of the screen.
+    pub fn draw(&self, ctx: &mut Context) -> GameResult<()> {
+        let image = self.canvas.get_image();
+        let scale = (MAX_SIZE / image.width() as f32).min(MAX_SIZE / image.height() as f32);
+        let param = Layout::new(ctx).place(
+            Anchor::BottomRight,
+            Vector2::new(-MARGIN, -MARGIN),
+            DrawParam {
+                offset: Point2::new(1.0, 1.0),
+                scale: Point2::new(scale, scale),
+                color: Some(Color::new(1.0, 1.0, 1.0, 1.0 - TRANSPARENCY)),
+                ..Default::default()
+            },
+        );
+        graphics::draw_ex(ctx, &self.canvas, param)
+    }
+}
+
+/// The size of the level on the canvas, without the frame, in pixels.
+fn level_size(world: &World) -> Vector2 {
+    Vector2::new(world.width as f32, world.height as f32) * PIXELS_PER_TILE
+}
+
+/// Where a position in the level is on the canvas, in pixels.
+fn canvas_position(position: Point2) -> Point2 {
+    Point2::new(
+        position[0] * PIXELS_PER_TILE + FRAME,
+        position[1] * PIXELS_PER_TILE + FRAME,
+    )
+}
+
+/// The canvas pixel a position in the level falls in.
+fn pixel(position: Point2) -> [i32; 2] {
+    let position = canvas_position(position);
+    [position[0].floor() as i32, position[1].floor() as i32]
+}
+
+/// The square of `size` tiles around `centre`, in pixels of the canvas.
+fn square(centre: Point2, size: f32) -> Rect {
+    let top_left = canvas_position(centre) - Vector2::new(size, size) * PIXELS_PER_TILE / 2.0;
+    Rect::new(
+        top_left[0],
+        top_left[1],
+        size * PIXELS_PER_TILE,
+        size * PIXELS_PER_TILE,
+    )
+}
+
+fn fill(ctx: &mut Context, color: [f32; 4], rect: Rect) -> GameResult<()> {
+    graphics::set_color(ctx, Color::new(color[0], color[1], color[2], color[3]))?;
+    graphics::rectangle(ctx, DrawMode::Fill, rect)
+}
@@ -0,0 +1,289 @@
+use ggez::graphics::Vector2;
+use ggez::{GameError, GameResult};
+
+use std::io::{BufRead, BufReader, Read, Write};
+
+use config::Config;
+use world::{BagAction, Input, TICK_TIME};
+
+/// The first line of every replay. The number goes up whenever the format changes.
+const HEADER: &str = "too-much-post replay 9";
+/// The start of the header, which is the same for every version.
+const HEADER_PREFIX: &str = "too-much-post replay ";
+
+/// The input of the game changing at the start of a tick.
+#[derive(Clone, Copy, Debug)]
+pub struct RecordedInput {
+    pub tick: u64,
+    pub input: Input,
+}
+
+/// Everything needed to play back a single game: the seed, the level and config it was played
+/// with, the number of ticks it lasted and every change of the input during it. The input is
+/// recorded after the controls are mapped, so replays don't depend on the key bindings.
+#[derive(Clone, Debug)]
+pub struct Replay {
+    pub seed: u64,
+    /// The level file the game was played on, or `None` for a generated level.
+    pub level: Option<String>,
+    /// The hash of the config the game was played with.
+    pub config_hash: u64,
+    pub ticks: u64,
+    pub inputs: Vec<RecordedInput>,
+    /// The tick the game ended at and the final score, if the game was finished.
+    pub result: Option<(u64, u32)>,
+}
+
+impl Replay {
+    pub fn new(seed: u64, level: Option<String>, config: &Config) -> Replay {
+        Replay {
+            seed,
+            level,
+            config_hash: config.hash(),
+            ticks: 0,
+            inputs: Vec::new(),
+            result: None,
+        }
+    }
+
+    /// Check that the replay is played back on the level and with the config it was recorded
+    /// with, as it would play differently otherwise.
+    pub fn check_setup(&self, level: Option<&str>, config: &Config) -> GameResult<()> {
+        let error = |message: String| {
+            Err(GameError::ResourceLoadError(format!(
+                "Can't play back replay: {}",
+                message
+            )))
+        };
+        if self.level.as_ref().map(String::as_str) != level {
+            return error(format!(
+                "it was recorded on {}, but the game is started on {}",
+                describe_level(self.level.as_ref().map(String::as_str)),
+                describe_level(level)
+            ));
+        }
+        if self.config_hash != config.hash() {
+            return error("it was recorded with a different config".to_string());
+        }
+        Ok(())
+    }
+
+    /// How the result of playing the replay back compares to the recorded result.
+    pub fn describe_result(&self, result: (u64, u32)) -> String {
+        match self.result {
+            Some(expected) if expected == result => format!(
+                "Replay finished with score {} at tick {}, as recorded",
+                result.1, result.0
+            ),
+            Some(expected) => format!(
+                "Replay diverged: expected score {} at tick {}, got score {} at tick {}",
+                expected.1, expected.0, result.1, result.0
+            ),
+            None => format!(
+                "Replay finished with score {} at tick {}",
+                result.1, result.0
+            ),
+        }
+    }
+
+    /// Record the next tick and the input it was simulated with.
+    pub fn record_tick(&mut self, input: Input) {
+        let previous = self
+            .inputs
+            .last()
+            .map(|recorded| recorded.input)
+            .unwrap_or_default();
+        if input != previous {
+            self.inputs.push(RecordedInput {
+                tick: self.ticks,
+                input,
+            });
+        }
+        self.ticks += 1;
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> GameResult<()> {
+        writeln!(writer, "{}", HEADER)?;
+        writeln!(writer, "seed {}", self.seed)?;
+        if let Some(ref level) = self.level {
+            writeln!(writer, "level {}", level)?;
+        }
+        writeln!(writer, "config {}", self.config_hash)?;
+        writeln!(writer, "tick_time {}", TICK_TIME)?;
+        writeln!(writer, "ticks {}", self.ticks)?;
+        for recorded in &self.inputs {
+            let direction = recorded.input.direction;
+            write!(
+                writer,
+                "input {} {} {}",
+                recorded.tick, direction[0], direction[1]
+            )?;
+            match recorded.input.bag {
+                Some(action) => writeln!(writer, " {}", action.name())?,
+                None => writeln!(writer)?,
+            }
+        }
+        if let Some((tick, score)) = self.result {
+            writeln!(writer, "result {} {}", tick, score)?;
+        }
+        Ok(())
+    }
+
+    pub fn read<R: Read>(reader: R) -> GameResult<Replay> {
+        let mut lines = BufReader::new(reader).lines();
+        match lines.next() {
+            Some(Ok(ref header)) if header == HEADER => {}
+            Some(Ok(ref header)) if header.starts_with(HEADER_PREFIX) => {
+                return Err(replay_error(&format!(
+                    "version {} isn't supported",
+                    &header[HEADER_PREFIX.len()..]
+                )))
+            }
+            _ => return Err(replay_error("not a replay file")),
+        }
+
+        let mut replay = Replay::new(0, None, &Config::default());
+        let mut has_seed = false;
+        let mut has_config = false;
+        for line in lines {
+            let line = line?;
+            // The level path is the rest of the line, as it may contain spaces
+            if line.starts_with("level ") {
+                replay.level = Some(line["level ".len()..].to_string());
+                continue;
+            }
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                [] => {}
+                ["seed", seed] => {
+                    replay.seed = parse(seed)?;
+                    has_seed = true;
+                }
+                ["config", hash] => {
+                    replay.config_hash = parse(hash)?;
+                    has_config = true;
+                }
+                ["tick_time", tick_time] => {
+                    if parse::<f32>(tick_time)? != TICK_TIME {
+                        return Err(replay_error("it was recorded with a different tick rate"));
+                    }
+                }
+                ["ticks", ticks] => replay.ticks = parse(ticks)?,
+                ["input", tick, x, y] => replay.inputs.push(RecordedInput {
+                    tick: parse(tick)?,
+                    input: Input {
+                        direction: Vector2::new(parse(x)?, parse(y)?),
+                        bag: None,
+                    },
+                }),
+                ["input", tick, x, y, action] => replay.inputs.push(RecordedInput {
+                    tick: parse(tick)?,
+                    input: Input {
+                        direction: Vector2::new(parse(x)?, parse(y)?),
+                        bag: Some(BagAction::from_name(action).ok_or_else(|| {
+                            replay_error(&format!("unknown bag action \"{}\"", action))
+                        })?),
+                    },
+                }),
+                ["result", tick, score] => replay.result = Some((parse(tick)?, parse(score)?)),
+                _ => return Err(replay_error(&format!("unexpected line \"{}\"", line))),
+            }
+        }
+
+        if !has_seed {
+            return Err(replay_error("the seed is missing"));
+        }
+        if !has_config {
+            return Err(replay_error("the config hash is missing"));
+        }
+        Ok(replay)
+    }
+}
+
+/// Feeds a recorded replay back into the game, tick by tick.
+pub struct Playback {
+    replay: Replay,
+    next_input: usize,
+    input: Input,
+}
+
+impl Playback {
+    pub fn new(replay: Replay) -> Playback {
+        Playback {
+            replay,
+            next_input: 0,
+            input: Input::default(),
+        }
+    }
+
+    pub fn replay(&self) -> &Replay {
+        &self.replay
+    }
+
+    /// Whether the recording still contains the given tick.
+    pub fn has_tick(&self, tick: u64) -> bool {
+        tick < self.replay.ticks
+    }
+
+    /// The input the given tick was simulated with. Ticks have to be asked for in order.
+    pub fn input_for_tick(&mut self, tick: u64) -> Input {
+        while let Some(recorded) = self.replay.inputs.get(self.next_input) {
+            if recorded.tick > tick {
+                break;
+            }
+            self.input = recorded.input;
+            self.next_input += 1;
+        }
+        self.input
+    }
+}
+
+fn parse<T: ::std::str::FromStr>(word: &str) -> GameResult<T> {
+    word.parse()
+        .map_err(|_| replay_error(&format!("invalid number \"{}\"", word)))
+}
+
+fn describe_level(level: Option<&str>) -> String {
+    match level {
+        Some(level) => format!("level {}", level),
+        None => "a generated level".to_string(),
+    }
+}
+
+fn replay_error(message: &str) -> GameError {
+    GameError::ResourceLoadError(format!("Could not read replay: {}", message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn setup_is_checked() {
+        let config = Config::default();
+        let replay = Replay::new(5, Some("/levels/main street.toml".to_string()), &config);
+        let mut file = Vec::new();
+        replay.write(&mut file).unwrap();
+        let read = Replay::read(&file[..]).unwrap();
+        assert_eq!(read.level, replay.level);
+        assert!(read
+            .check_setup(Some("/levels/main street.toml"), &config)
+            .is_ok());
+        assert!(read.check_setup(None, &config).is_err());
+
+        let mut changed = config.clone();
+        changed.sorted_bag = !changed.sorted_bag;
+        assert!(read
+            .check_setup(Some("/levels/main street.toml"), &changed)
+            .is_err());
+    }
+
+    #[test]
+    fn other_versions_are_rejected() {
+        let file = "too-much-post replay 8\nseed 5\n";
+        match Replay::read(file.as_bytes()) {
+            Err(GameError::ResourceLoadError(message)) => assert!(message.contains("version 8")),
+            _ => panic!("an old replay was read"),
+        }
+    }
+}
@@ -0,0 +1,222 @@
+use config::Config;
+use world::Letter;
+
+/// Where the points of a game came from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Breakdown {
+    /// What the delivered letters are worth by their kind.
+    pub letters: u32,
+    /// Earned by delivering letters before they were due.
+    pub on_time: u32,
+    /// Earned by delivering letters soon after they appeared.
+    pub speed: u32,
+    /// Earned by delivering several letters to a house at once.
+    pub multiplier: u32,
+    /// Earned by chaining deliveries quickly after each other.
+    pub combo: u32,
+    /// Lost to fragile letters breaking.
+    pub penalties: u32,
+}
+
+impl Breakdown {
+    pub fn total(&self) -> u32 {
+        self.letters + self.on_time + self.speed + self.multiplier + self.combo - self.penalties
+    }
+}
+
+/// What a single delivery at a house earned.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Delivery {
+    pub points: u32,
+    /// The number of letters handed over at once.
+    pub letters: u32,
+    /// How many deliveries in a row the combo is at, counting this one.
+    pub combo: u32,
+}
+
+/// Keeps the score of a game. Letters are worth their kind's score, a bonus for being on time
+/// and a bonus for being delivered quickly. Handing over several letters at once multiplies that, and deliveries that follow
+/// each other within `combo_time` seconds build up a combo that adds to every delivery.
+#[derive(Clone, Debug, Default)]
+pub struct Scoring {
+    pub breakdown: Breakdown,
+    /// The number of deliveries in the current combo, or 0 before the first.
+    pub combo: u32,
+    /// The time of the last delivery, if there was one.
+    last_delivery: Option<f64>,
+}
+
+impl Scoring {
+    pub fn new() -> Scoring {
+        Scoring::default()
+    }
+
+    pub fn score(&self) -> u32 {
+        self.breakdown.total()
+    }
+
+    /// Whether the combo still continues at `time`.
+    pub fn combo_active(&self, config: &Config, time: f64) -> bool {
+        match self.last_delivery {
+            Some(last) => time - last <= config.combo_time as f64,
+            None => false,
+        }
+    }
+
+    /// Score the letters that were handed over at a house in the same tick.
+    pub fn deliver(&mut self, config: &Config, letters: &[Letter], time: f64) -> Delivery {
+        self.combo = if self.combo_active(config, time) {
+            self.combo + 1
+        } else {
+            1
+        };
+        self.last_delivery = Some(time);
+
+        let base: u32 = letters
+            .iter()
+            .map(|letter| letter.score(config, time))
+            .sum();
+        let on_time: u32 = letters
+            .iter()
+            .map(|letter| letter.on_time_bonus(config, time))
+            .sum();
+        let speed: u32 = letters
+            .iter()
+            .map(|letter| speed_bonus(config, letter, time))
+            .sum();
+        let earned = base + on_time + speed;
+        let extra_letters = letters.len().saturating_sub(1) as f32;
+        let multiplier =
+            (earned as f32 * extra_letters * config.multiple_delivery_bonus).round() as u32;
+        let combo = (self.combo - 1) * config.combo_bonus;
+
+        self.breakdown.letters += base;
+        self.breakdown.on_time += on_time;
+        self.breakdown.speed += speed;
+        self.breakdown.multiplier += multiplier;
+        self.breakdown.combo += combo;
+        Delivery {
+            points: earned + multiplier + combo,
+            letters: letters.len() as u32,
+            combo: self.combo,
+        }
+    }
+
+    /// Take `points` off the score, as far as there is score left.
+    pub fn penalise(&mut self, points: u32) {
+        self.breakdown.penalties += points.min(self.score());
+    }
+}
+
+/// The bonus for delivering `letter` at `time`, which shrinks from `speed_bonus` when it appears
+/// to nothing `speed_time` seconds later.
+fn speed_bonus(config: &Config, letter: &Letter, time: f64) -> u32 {
+    let left = 1.0 - letter.age(time) / config.speed_time as f64;
+    (config.speed_bonus as f64 * left.max(0.0)).ceil() as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ggez::graphics::Point2;
+    use world::LetterKind;
+
+    /// A config where letters are only worth their kind's score, so each test can turn on the
+    /// bonus it checks.
+    fn config() -> Config {
+        Config {
+            on_time_bonus: 0,
+            speed_bonus: 0,
+            multiple_delivery_bonus: 0.0,
+            combo_bonus: 0,
+            ..Config::default()
+        }
+    }
+
+    fn letter(spawn_time: f64) -> Letter {
+        Letter {
+            number: 1,
+            kind: LetterKind::Normal,
+            position: Point2::new(0.0, 0.0),
+            spawn_time,
+            due_time: spawn_time + 60.0,
+            dropped_time: 0.0,
+        }
+    }
+
+    #[test]
+    fn combo_continues_within_combo_time() {
+        let config = Config {
+            combo_time: 10.0,
+            combo_bonus: 2,
+            ..config()
+        };
+        let mut scoring = Scoring::new();
+        assert_eq!(scoring.deliver(&config, &[letter(0.0)], 1.0).combo, 1);
+        let delivery = scoring.deliver(&config, &[letter(0.0)], 6.0);
+        assert_eq!(delivery.combo, 2);
+        assert_eq!(delivery.points, 1 + 2);
+        assert_eq!(scoring.deliver(&config, &[letter(0.0)], 16.0).combo, 3);
+        assert!(scoring.combo_active(&config, 26.0));
+        assert!(!scoring.combo_active(&config, 27.0));
+
+        let delivery = scoring.deliver(&config, &[letter(0.0)], 27.0);
+        assert_eq!(delivery.combo, 1);
+        assert_eq!(delivery.points, 1);
+        assert_eq!(scoring.breakdown.combo, 2 + 4);
+    }
+
+    #[test]
+    fn several_letters_at_once_multiply() {
+        let config = Config {
+            multiple_delivery_bonus: 0.5,
+            ..config()
+        };
+        let mut scoring = Scoring::new();
+        let delivery = scoring.deliver(&config, &[letter(0.0)], 1.0);
+        assert_eq!(delivery.points, 1);
+        assert_eq!(scoring.breakdown.multiplier, 0);
+
+        let mut scoring = Scoring::new();
+        let letters = [letter(0.0), letter(0.0), letter(0.0)];
+        let delivery = scoring.deliver(&config, &letters, 1.0);
+        assert_eq!(delivery.letters, 3);
+        assert_eq!(scoring.breakdown.letters, 3);
+        assert_eq!(scoring.breakdown.multiplier, 3);
+        assert_eq!(delivery.points, 6);
+    }
+
+    #[test]
+    fn speed_bonus_shrinks_with_age() {
+        let config = Config {
+            speed_bonus: 4,
+            speed_time: 20.0,
+            ..config()
+        };
+        let speed = |time| {
+            let mut scoring = Scoring::new();
+            scoring.deliver(&config, &[letter(10.0)], time);
+            scoring.breakdown.speed
+        };
+        assert_eq!(speed(10.0), 4);
+        assert_eq!(speed(20.0), 2);
+        assert_eq!(speed(25.0), 1);
+        assert_eq!(speed(30.0), 0);
+        assert_eq!(speed(60.0), 0);
+    }
+
+    #[test]
+    fn penalties_stop_at_zero() {
+        let config = config();
+        let mut scoring = Scoring::new();
+        scoring.deliver(&config, &[letter(0.0), letter(0.0)], 1.0);
+        assert_eq!(scoring.score(), 2);
+        scoring.penalise(1);
+        assert_eq!(scoring.score(), 1);
+        scoring.penalise(3);
+        assert_eq!(scoring.score(), 0);
+        assert_eq!(scoring.breakdown.penalties, 2);
+        scoring.penalise(1);
+        assert_eq!(scoring.score(), 0);
+    }
+}
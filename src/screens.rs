@@ -0,0 +1,129 @@
+use ggez::event::Keycode;
+use ggez::graphics::{DrawParam, Drawable, Point2, Text, Vector2};
+use ggez::*;
+
+use game_state::{InputEvent, Shared, State, Transition};
+use highscores;
+use input::Action;
+use layout::{Anchor, Layout};
+use ui;
+
+/// Whether the event leaves an information screen. H also goes back, because it opens the high
+/// scores from the splash screen.
+fn is_back(event: &InputEvent, shared: &Shared) -> bool {
+    let controls = &shared.controls;
+    match *event {
+        InputEvent::KeyDown {
+            keycode: Keycode::H,
+            ..
+        } => true,
+        _ => controls.pressed(event, Action::Back) || controls.pressed(event, Action::Confirm),
+    }
+}
+
+fn draw_back_text(ctx: &mut Context, back_text: &Text) -> GameResult<()> {
+    let layout = Layout::new(ctx);
+    back_text.draw_ex(
+        ctx,
+        layout.place(
+            Anchor::Bottom,
+            Vector2::new(0.0, -50.0),
+            DrawParam {
+                offset: Point2::new(0.5, 0.5),
+                ..Default::default()
+            },
+        ),
+    )
+}
+
+/// The high score table, reachable from the splash screen and the main menu.
+pub struct HighScoreScreen {
+    texts: Vec<Text>,
+    back_text: Text,
+}
+
+impl HighScoreScreen {
+    pub fn new(ctx: &mut Context, shared: &Shared) -> GameResult<HighScoreScreen> {
+        Ok(HighScoreScreen {
+            texts: highscores::table_texts(ctx, &shared.high_scores)?,
+            back_text: ui::text(ctx, "Press Escape to go back.")?,
+        })
+    }
+}
+
+impl<'a> State<'a> for HighScoreScreen {
+    fn draw(&mut self, ctx: &mut Context, _shared: &Shared<'a>) -> GameResult<()> {
+        highscores::draw_table(ctx, &self.texts, Vector2::new(0.0, 150.0), None)?;
+        draw_back_text(ctx, &self.back_text)
+    }
+
+    fn input(
+        &mut self,
+        _ctx: &mut Context,
+        shared: &mut Shared<'a>,
+        event: &InputEvent,
+    ) -> GameResult<Transition<'a>> {
+        Ok(if is_back(event, shared) {
+            Transition::Pop
+        } else {
+            Transition::None
+        })
+    }
+}
+
+const CREDITS: &[&str] = &[
+    "Too Much Post, Out Of Space",
+    "Made by Thomas den Hollander for Ludum Dare 42",
+    "Built with ggez",
+];
+
+pub struct CreditsScreen {
+    texts: Vec<Text>,
+    back_text: Text,
+}
+
+impl CreditsScreen {
+    pub fn new(ctx: &mut Context) -> GameResult<CreditsScreen> {
+        let mut texts = Vec::new();
+        for line in CREDITS {
+            texts.push(ui::text(ctx, line)?);
+        }
+        Ok(CreditsScreen {
+            texts,
+            back_text: ui::text(ctx, "Press Escape to go back.")?,
+        })
+    }
+}
+
+impl<'a> State<'a> for CreditsScreen {
+    fn draw(&mut self, ctx: &mut Context, _shared: &Shared<'a>) -> GameResult<()> {
+        let layout = Layout::new(ctx);
+        for (i, text) in self.texts.iter().enumerate() {
+            text.draw_ex(
+                ctx,
+                layout.place(
+                    Anchor::Top,
+                    Vector2::new(0.0, 200.0 + 50.0 * i as f32),
+                    DrawParam {
+                        offset: Point2::new(0.5, 0.5),
+                        ..Default::default()
+                    },
+                ),
+            )?;
+        }
+        draw_back_text(ctx, &self.back_text)
+    }
+
+    fn input(
+        &mut self,
+        _ctx: &mut Context,
+        shared: &mut Shared<'a>,
+        event: &InputEvent,
+    ) -> GameResult<Transition<'a>> {
+        Ok(if is_back(event, shared) {
+            Transition::Pop
+        } else {
+            Transition::None
+        })
+    }
+}
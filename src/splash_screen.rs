@@ -1,209 +1,186 @@
-use ggez::event::EventHandler;
-use ggez::graphics::DrawParam;
-use ggez::graphics::Drawable;
-use ggez::graphics::FilterMode;
-use ggez::graphics::Point2;
-use ggez::graphics::{ Text, Rect };
+use ggez::graphics::Vector2;
 use ggez::*;
+use animation;
+use game_state::{InputEvent, Shared, State, Transition};
 use images::Images;
-use ggez::event::{ Keycode, Mod };
+use input::Action;
+use layout::Anchor;
+use ggez::event::Keycode;
+use menu::MainMenu;
+use screens::HighScoreScreen;
+use ui::{Label, ProgressBar, Sprite, SpriteSource, Ui, Widget, WidgetId};
+
+/// Add a line of text with its top left `offset` reference pixels from the centre of the
+/// screen.
+fn add_text(ctx: &mut Context, ui: &mut Ui, offset: Vector2, contents: &str) -> GameResult<()> {
+    let label = Label::new(ctx, contents)?;
+    ui.add(
+        Anchor::Centre,
+        offset,
+        Vector2::new(0.0, 0.0),
+        Widget::Label(label),
+    );
+    Ok(())
+}
 
 pub struct SplashScreen<'a> {
     duration: f64,
-    pub images: &'a Images,
-    main_text: Text,
-    sub_text: Text,
-
-    pick_up_letters_text: Text,
-    deliver_text: Text,
-    hold_four_text: Text,
-    game_over_text: Text,
-    spacebar_text: Text,
-
-    spacebar_pressed: bool
+    images: &'a Images,
+    /// The title, shown first.
+    intro: Ui,
+    house: WidgetId,
+    /// How to play, shown after the title.
+    tutorial: Ui,
+    progress_bar: WidgetId,
 }
 
 impl<'a> SplashScreen<'a> {
     pub fn new(ctx: &mut Context, images: &'a Images) -> GameResult<SplashScreen<'a>> {
-        let mut main_text = Text::new(
+        // Everything is placed around the centre of the screen, except the hint at the bottom
+        let top_left = Vector2::new(0.0, 0.0);
+        let clip = |name: &str, scale| {
+            Widget::Sprite(Sprite::new(SpriteSource::Clip(name.to_string()), scale))
+        };
+        // Only the first house is sure to exist, so the others wrap around to it
+        let house_clip = |index: usize| {
+            let name = animation::house_clip_name(index % images.house_count());
+            clip(&name, 2.0)
+        };
+
+        let mut intro = Ui::new();
+        let house = intro.add(
+            Anchor::Centre,
+            Vector2::new(-300.0, -64.0),
+            top_left,
+            house_clip(2),
+        );
+        let title = Label::new(ctx, "Too Much Post, Out Of Space")?.with_scale(2.0);
+        intro.add(
+            Anchor::Centre,
+            Vector2::new(-150.0, -30.0),
+            top_left,
+            Widget::Label(title),
+        );
+        add_text(
             ctx,
-            &"Too Much Post, Out Of Space",
-            &graphics::Font::default_font()?,
+            &mut intro,
+            Vector2::new(-130.0, 20.0),
+            "Thomas den Hollander (Ludum Dare 42)",
         )?;
-        main_text.set_filter(FilterMode::Nearest);
 
-        let mut sub_text = Text::new(
+        let mut tutorial = Ui::new();
+        add_text(
             ctx,
-            &"Thomas den Hollander (Ludum Dare 42)",
-            &graphics::Font::default_font()?,
+            &mut tutorial,
+            Vector2::new(-60.0, -215.0),
+            "Pick up letters...",
         )?;
-        sub_text.set_filter(FilterMode::Nearest);
-
-        let mut pick_up_letters_text = Text::new(
+        add_text(
             ctx,
-            &"Pick up letters...",
-            &graphics::Font::default_font()?,
+            &mut tutorial,
+            Vector2::new(-200.0, -100.0),
+            "...and deliver them to the correct houses.",
         )?;
-        pick_up_letters_text.set_filter(FilterMode::Nearest);
-
-        let mut deliver_text = Text::new(
+        add_text(
             ctx,
-            &"...and deliver them to the correct houses.",
-            &graphics::Font::default_font()?,
+            &mut tutorial,
+            Vector2::new(-60.0, 15.0),
+            "You'll drop them if you carry too many!",
         )?;
-        deliver_text.set_filter(FilterMode::Nearest);
-
-        let mut game_over_text = Text::new(
+        add_text(
             ctx,
-            &"The game ends when the ground is full...",
-            &graphics::Font::default_font()?,
+            &mut tutorial,
+            Vector2::new(-200.0, 150.0),
+            "The game ends when the ground is full...",
         )?;
-        game_over_text.set_filter(FilterMode::Nearest);
-
-        let mut hold_four_text = Text::new(
-            ctx,
-            &"You'll drop them if you carry too many!",
-            &graphics::Font::default_font()?,
-        )?;
-        hold_four_text.set_filter(FilterMode::Nearest);
-
-        let mut spacebar_text = Text::new(
-            ctx,
-            &"Press Space to continue.",
-            &graphics::Font::default_font()?,
-        )?;
-        spacebar_text.set_filter(FilterMode::Nearest);
+        tutorial.add(
+            Anchor::Centre,
+            Vector2::new(-200.0, -240.0),
+            top_left,
+            clip("letter", 2.0),
+        );
+        tutorial.add(
+            Anchor::Centre,
+            Vector2::new(150.0, -155.0),
+            top_left,
+            house_clip(3),
+        );
+        tutorial.add(
+            Anchor::Centre,
+            Vector2::new(-200.0, -20.0),
+            top_left,
+            clip("walk_down", 2.5),
+        );
+        let progress_bar = tutorial.add(
+            Anchor::Centre,
+            Vector2::new(150.0, 150.0),
+            Vector2::new(0.0, 0.5),
+            Widget::ProgressBar(ProgressBar::new(
+                images.progress_bar,
+                images.progress_bar_filled,
+                Vector2::new(1.0, 1.0),
+            )),
+        );
+        let continue_text = Label::new(ctx, "Press Space to continue, or H for the high scores.")?;
+        tutorial.add(
+            Anchor::Bottom,
+            Vector2::new(0.0, -50.0),
+            Vector2::new(0.5, 0.5),
+            Widget::Label(continue_text),
+        );
 
         Ok(SplashScreen {
             duration: 0.0,
             images,
-            main_text,
-            sub_text,
-
-            pick_up_letters_text,
-            deliver_text,
-            hold_four_text,
-            game_over_text,
-            spacebar_text,
-
-            spacebar_pressed: false
+            intro,
+            house,
+            tutorial,
+            progress_bar,
         })
     }
-
-    pub fn should_start(&self) -> bool {
-        self.spacebar_pressed
-    }
 }
 
-impl<'a> EventHandler for SplashScreen<'a> {
-    fn update(&mut self, ctx: &mut Context) -> GameResult<()> {
+impl<'a> State<'a> for SplashScreen<'a> {
+    fn update(
+        &mut self,
+        ctx: &mut Context,
+        _shared: &mut Shared<'a>,
+    ) -> GameResult<Transition<'a>> {
         self.duration += timer::duration_to_f64(timer::get_delta(ctx));
-        Ok(())
+        let time = timer::duration_to_f64(timer::get_time_since_start(ctx));
+        let dy = (time * 5.0).sin() as f32 * 20.0;
+        self.intro
+            .set_offset(self.house, Vector2::new(-300.0, -64.0 + dy));
+        self.tutorial
+            .get_mut(self.progress_bar)
+            .progress_bar_mut()
+            .set_value((time - time.floor()) as f32);
+        Ok(Transition::None)
     }
 
-    fn draw(&mut self, ctx: &mut Context) -> GameResult<()> {
-        graphics::clear(ctx);
+    fn draw(&mut self, ctx: &mut Context, _shared: &Shared<'a>) -> GameResult<()> {
+        let time = timer::duration_to_f64(timer::get_time_since_start(ctx)) as f32;
         if self.duration < 3.0 {
-            let dy =
-                (timer::duration_to_f64(timer::get_time_since_start(ctx)) * 5.0).sin() as f32 * 20.0;
-            self.images.houses[2].draw_ex(
-                ctx,
-                DrawParam {
-                    dest: Point2::new(100.0, 300.0 - 64.0 + dy),
-                    scale: Point2::new(2.0, 2.0),
-                    ..Default::default()
-                },
-            )?;
-
-            self.main_text.draw_ex(
-                ctx,
-                DrawParam {
-                    dest: Point2::new(250.0, 270.0),
-                    scale: Point2::new(2.0, 2.0),
-                    ..Default::default()
-                },
-            )?;
-            self.sub_text.draw_ex(
-                ctx,
-                DrawParam {
-                    dest: Point2::new(270.0, 320.0),
-                    ..Default::default()
-                },
-            )?;
+            self.intro.draw(ctx, self.images, time)
         } else {
-            self.pick_up_letters_text.draw(ctx, Point2::new(340.0, 85.0), 0.0)?;
-            self.images.letter.draw_ex(ctx, DrawParam {
-                dest: Point2::new(200.0, 60.0),
-                scale: Point2::new(2.0, 2.0),
-                ..Default::default()
-            })?;
-            self.deliver_text.draw(ctx, Point2::new(200.0, 200.0), 0.0)?;
-            self.images.houses[3].draw_ex(ctx, DrawParam {
-                dest: Point2::new(550.0, 145.0),
-                scale: Point2::new(2.0, 2.0),
-                ..Default::default()
-            })?;
-            self.hold_four_text.draw(ctx, Point2::new(340.0, 315.0), 0.0)?;
-            self.images.player_front.draw_ex(ctx, DrawParam {
-                dest: Point2::new(200.0, 280.0),
-                scale: Point2::new(2.5, 2.5),
-                ..Default::default()
-            })?;
-            self.game_over_text.draw(ctx, Point2::new(200.0, 450.0), 0.0)?;
-
-            // Draw progress bar
-            let progress_bar_dest = Point2::new(550.0, 450.0);
-            self.images.progress_bar.draw_ex(
-                ctx,
-                DrawParam {
-                    dest: progress_bar_dest,
-                    rotation: 0.0,
-                    offset: Point2::new(0.0, 0.5),
-                    shear: Point2::new(0.0, 0.0),
-                    ..Default::default()
-                },
-            )?;
-            let time = timer::duration_to_f64(timer::get_time_since_start(ctx));
-            let fraction = (time - time.floor()) as f32;
-            self.images.progress_bar_filled.draw_ex(
-                ctx,
-                DrawParam {
-                    src: Rect {
-                        x: 0.0,
-                        y: 0.0,
-                        w: fraction,
-                        h: 1.0,
-                    },
-                    dest: progress_bar_dest,
-                    rotation: 0.0,
-                    offset: Point2::new(0.0, 0.5),
-                    shear: Point2::new(0.0, 0.0),
-                    ..Default::default()
-                },
-            )?;
-            self.spacebar_text.draw_ex(
-                ctx,
-                DrawParam {
-                    dest: Point2::new(400.0, 550.0),
-                    rotation: 0.0,
-                    offset: Point2::new(0.5, 0.5),
-                    ..Default::default()
-                },
-            )?;
+            self.tutorial.draw(ctx, self.images, time)
         }
-        graphics::present(ctx);
-        Ok(())
     }
 
-    fn key_down_event(
+    fn input(
         &mut self,
-        _ctx: &mut Context,
-        keycode: Keycode,
-        _keymod: Mod,
-        _repeat: bool,
-    ) {
-        if keycode == Keycode::Space {
-            self.spacebar_pressed = true;
-        }
+        ctx: &mut Context,
+        shared: &mut Shared<'a>,
+        event: &InputEvent,
+    ) -> GameResult<Transition<'a>> {
+        Ok(match *event {
+            InputEvent::KeyDown { keycode: Keycode::H, .. } => {
+                Transition::Push(Box::new(HighScoreScreen::new(ctx, shared)?))
+            }
+            _ if shared.controls.pressed(event, Action::Confirm) => {
+                Transition::Replace(Box::new(MainMenu::new(ctx)?))
+            }
+            _ => Transition::None,
+        })
     }
 }
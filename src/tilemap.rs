@@ -0,0 +1,236 @@
+use ggez::graphics::Point2;
+
+use std::collections::VecDeque;
+
+use world::{Bounds, PLAYER_HALF_SIZE};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Terrain {
+    Grass,
+    Road,
+    Water,
+    Fence,
+    Tree,
+}
+
+impl Terrain {
+    pub const ALL: [Terrain; 5] = [
+        Terrain::Grass,
+        Terrain::Road,
+        Terrain::Water,
+        Terrain::Fence,
+        Terrain::Tree,
+    ];
+
+    /// The character used for this terrain in level files.
+    pub fn symbol(self) -> char {
+        match self {
+            Terrain::Grass => '.',
+            Terrain::Road => '=',
+            Terrain::Water => '~',
+            Terrain::Fence => '#',
+            Terrain::Tree => 'T',
+        }
+    }
+
+    pub fn from_symbol(symbol: char) -> Option<Terrain> {
+        Terrain::ALL.iter().cloned().find(|t| t.symbol() == symbol)
+    }
+
+    pub fn walkable(self) -> bool {
+        match self {
+            Terrain::Grass | Terrain::Road => true,
+            Terrain::Water | Terrain::Fence | Terrain::Tree => false,
+        }
+    }
+
+    /// What the speed of the player is multiplied with when walking on this terrain.
+    pub fn speed_modifier(self) -> f32 {
+        match self {
+            Terrain::Road => 1.4,
+            _ => 1.0,
+        }
+    }
+}
+
+/// The ground of a level, as a grid of one tile per square.
+#[derive(Clone, Debug)]
+pub struct TileMap {
+    pub width: u32,
+    pub height: u32,
+    tiles: Vec<Terrain>,
+}
+
+impl TileMap {
+    pub fn new(width: u32, height: u32, terrain: Terrain) -> TileMap {
+        TileMap {
+            width,
+            height,
+            tiles: vec![terrain; (width * height) as usize],
+        }
+    }
+
+    /// Read a map from rows of terrain symbols, as they appear in level files.
+    pub fn parse(width: u32, height: u32, rows: &[String]) -> Result<TileMap, String> {
+        if rows.len() != height as usize {
+            return Err(format!(
+                "there are {} rows of tiles, but the level is {} tiles high",
+                rows.len(),
+                height
+            ));
+        }
+        let mut tiles = Vec::with_capacity((width * height) as usize);
+        for (y, row) in rows.iter().enumerate() {
+            if row.chars().count() != width as usize {
+                return Err(format!(
+                    "row {} of the tiles is {} tiles wide, but the level is {} tiles wide",
+                    y,
+                    row.chars().count(),
+                    width
+                ));
+            }
+            for symbol in row.chars() {
+                tiles.push(
+                    Terrain::from_symbol(symbol)
+                        .ok_or_else(|| format!("unknown tile '{}' in row {}", symbol, y))?,
+                );
+            }
+        }
+        Ok(TileMap {
+            width,
+            height,
+            tiles,
+        })
+    }
+
+    /// Write the map as rows of terrain symbols, the way `parse` reads it.
+    pub fn rows(&self) -> Vec<String> {
+        self.tiles
+            .chunks(self.width as usize)
+            .map(|row| row.iter().map(|terrain| terrain.symbol()).collect())
+            .collect()
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> Terrain {
+        self.tiles[(y * self.width + x) as usize]
+    }
+
+    pub fn set(&mut self, x: u32, y: u32, terrain: Terrain) {
+        self.tiles[(y * self.width + x) as usize] = terrain;
+    }
+
+    /// The terrain under the given point. Points outside the map count as the nearest edge tile.
+    pub fn terrain_at(&self, point: Point2) -> Terrain {
+        let x = (point[0].max(0.0) as u32).min(self.width - 1);
+        let y = (point[1].max(0.0) as u32).min(self.height - 1);
+        self.get(x, y)
+    }
+
+    /// The bounds of every tile that can't be walked on and overlaps the given bounds.
+    pub fn blocking_tiles(&self, bounds: &Bounds) -> Vec<Bounds> {
+        let min_x = bounds.min[0].max(0.0) as u32;
+        let min_y = bounds.min[1].max(0.0) as u32;
+        let max_x = (bounds.max[0].max(0.0) as u32).min(self.width - 1);
+        let max_y = (bounds.max[1].max(0.0) as u32).min(self.height - 1);
+        let mut blocking = Vec::new();
+        for y in min_y..=max_y {
+            for x in min_x..=max_x {
+                if !self.get(x, y).walkable() {
+                    blocking.push(TileMap::tile_bounds(x, y));
+                }
+            }
+        }
+        blocking
+    }
+
+    fn tile_bounds(x: u32, y: u32) -> Bounds {
+        Bounds {
+            min: Point2::new(x as f32, y as f32),
+            max: Point2::new(x as f32 + 1.0, y as f32 + 1.0),
+        }
+    }
+
+    /// Find every tile the player can walk to from `start`, moving between neighbouring tiles
+    /// that can be walked on and aren't covered by any of the `solids`.
+    pub fn reachable_from(&self, start: Point2, solids: &[Bounds]) -> Reachability {
+        let free = |x: u32, y: u32| {
+            self.get(x, y).walkable()
+                && solids
+                    .iter()
+                    .all(|solid| !TileMap::tile_bounds(x, y).overlaps(solid))
+        };
+        let mut reachable = vec![false; self.tiles.len()];
+        let start_x = (start[0].max(0.0) as u32).min(self.width - 1);
+        let start_y = (start[1].max(0.0) as u32).min(self.height - 1);
+        let mut queue = VecDeque::new();
+        reachable[(start_y * self.width + start_x) as usize] = true;
+        queue.push_back((start_x, start_y));
+        while let Some((x, y)) = queue.pop_front() {
+            let mut neighbours = Vec::new();
+            if x > 0 {
+                neighbours.push((x - 1, y));
+            }
+            if y > 0 {
+                neighbours.push((x, y - 1));
+            }
+            if x + 1 < self.width {
+                neighbours.push((x + 1, y));
+            }
+            if y + 1 < self.height {
+                neighbours.push((x, y + 1));
+            }
+            for (nx, ny) in neighbours {
+                let index = (ny * self.width + nx) as usize;
+                if !reachable[index] && free(nx, ny) {
+                    reachable[index] = true;
+                    queue.push_back((nx, ny));
+                }
+            }
+        }
+        Reachability {
+            width: self.width,
+            height: self.height,
+            reachable,
+        }
+    }
+}
+
+/// The tiles the player can walk to from some starting point.
+#[derive(Clone, Debug)]
+pub struct Reachability {
+    width: u32,
+    height: u32,
+    reachable: Vec<bool>,
+}
+
+impl Reachability {
+    pub fn contains(&self, point: Point2) -> bool {
+        if point[0] < 0.0 || point[1] < 0.0 {
+            return false;
+        }
+        let (x, y) = (point[0] as u32, point[1] as u32);
+        x < self.width && y < self.height && self.reachable[(y * self.width + x) as usize]
+    }
+
+    /// Whether the player can get closer than `reach` to the target on both axes.
+    pub fn can_reach(&self, target: Point2, reach: f32) -> bool {
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if !self.reachable[(y * self.width + x) as usize] {
+                    continue;
+                }
+                // The closest the centre of the player gets to the target in this tile
+                let closest_x = target[0]
+                    .max(x as f32 + PLAYER_HALF_SIZE)
+                    .min(x as f32 + 1.0 - PLAYER_HALF_SIZE);
+                let closest_y = target[1]
+                    .max(y as f32 + PLAYER_HALF_SIZE)
+                    .min(y as f32 + 1.0 - PLAYER_HALF_SIZE);
+                if (closest_x - target[0]).abs() < reach && (closest_y - target[1]).abs() < reach {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+}
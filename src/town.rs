@@ -0,0 +1,303 @@
+use rand::Rng;
+
+use config::Config;
+use level::{HouseSpec, Level};
+use tilemap::{Terrain, TileMap};
+use world::GameRng;
+
+/// How many times a town is laid out again when a house can't be reached, before giving up on
+/// the districts and leaving the back of the blocks empty.
+const MAX_ATTEMPTS: u32 = 20;
+
+/// The distance between two house plots along a street, in tiles.
+const PLOT_SPACING: u32 = 4;
+
+/// What fills the space behind the houses of a block.
+#[derive(Clone, Copy, Debug)]
+enum District {
+    Meadow,
+    Park,
+    Pond,
+    Garden,
+}
+
+/// A street running from the top to the bottom of the map, two tiles wide.
+#[derive(Clone, Copy, Debug)]
+struct CrossStreet {
+    /// The leftmost column of the street.
+    column: u32,
+}
+
+impl CrossStreet {
+    fn covers(&self, min_x: u32, max_x: u32) -> bool {
+        min_x < self.column + 2 && max_x > self.column
+    }
+}
+
+/// A place for a house along the main street.
+#[derive(Clone, Copy, Debug)]
+struct Plot {
+    x: u32,
+    north: bool,
+}
+
+/// The number of houses that fit along the main street of a town of the given width.
+pub fn max_house_count(width: u32) -> u32 {
+    2 * (width.saturating_sub(4) / PLOT_SPACING + 1)
+}
+
+/// Generate a town of the size in the config. A main street runs from west to east, with the
+/// houses on both sides of it and cross streets cutting the town into blocks. Houses are
+/// numbered along the main street, odd on the north side and even on the south side. Behind
+/// the houses every block gets a district: a meadow, a park, a pond or a fenced garden.
+pub fn generate(config: &Config, rng: &mut GameRng, resource_count: usize) -> Level {
+    generate_with_attempts(config, rng, resource_count, MAX_ATTEMPTS)
+}
+
+/// Generate a town, laying out the districts up to `attempts` times.
+fn generate_with_attempts(
+    config: &Config,
+    rng: &mut GameRng,
+    resource_count: usize,
+    attempts: u32,
+) -> Level {
+    let width = config.level_width;
+    let height = config.level_height;
+    let main_street = (height / 2 - 1 + rng.gen_range(0, 3) - 1)
+        .max(3)
+        .min(height - 5);
+
+    let mut cross_streets = Vec::new();
+    let cross_street_count = rng.gen_range(1, width / 12 + 1);
+    for i in 0..cross_street_count {
+        let centre = width * (i + 1) / (cross_street_count + 1);
+        let column = (centre + rng.gen_range(0, 3)).saturating_sub(2).max(1);
+        cross_streets.push(CrossStreet {
+            column: column.min(width - 3),
+        });
+    }
+    // Drop cross streets until all houses fit
+    let mut plots = find_plots(width, &cross_streets);
+    while plots.len() < config.house_count as usize && !cross_streets.is_empty() {
+        cross_streets.pop();
+        plots = find_plots(width, &cross_streets);
+    }
+    rng.shuffle(&mut plots);
+    plots.truncate(config.house_count as usize);
+    plots.sort_by_key(|plot| plot.x);
+
+    let mut houses = Vec::new();
+    let (mut next_odd, mut next_even) = (1, 2);
+    for plot in &plots {
+        let (y, number) = if plot.north {
+            next_odd += 2;
+            (main_street as f32 - 1.0, next_odd - 2)
+        } else {
+            next_even += 2;
+            (main_street as f32 + 3.0, next_even - 2)
+        };
+        houses.push(HouseSpec {
+            position: [plot.x as f32, y],
+            number,
+            sprite: Some(rng.gen_range(0, resource_count)),
+        });
+    }
+
+    let mut streets = TileMap::new(width, height, Terrain::Grass);
+    for x in 0..width {
+        streets.set(x, main_street, Terrain::Road);
+        streets.set(x, main_street + 1, Terrain::Road);
+    }
+    for street in &cross_streets {
+        for y in 0..height {
+            streets.set(street.column, y, Terrain::Road);
+            streets.set(street.column + 1, y, Terrain::Road);
+        }
+    }
+
+    let mut level = Level {
+        width,
+        height,
+        spawn: [width as f32 / 2.0, main_street as f32 + 1.0],
+        houses,
+        obstacles: Vec::new(),
+        tiles: Vec::new(),
+    };
+    for _ in 0..attempts {
+        let mut tiles = streets.clone();
+        add_districts(rng, &mut tiles, main_street, &cross_streets);
+        level.tiles = tiles.rows();
+        if level.unreachable_houses().is_empty() {
+            return level;
+        }
+    }
+    level.tiles = streets.rows();
+    level
+}
+
+/// Every plot along the main street that isn't in the way of a cross street.
+fn find_plots(width: u32, cross_streets: &[CrossStreet]) -> Vec<Plot> {
+    let mut plots = Vec::new();
+    let mut x = 2;
+    while x + 2 <= width {
+        if cross_streets
+            .iter()
+            .any(|street| street.covers(x - 1, x + 1))
+        {
+            x += 1;
+        } else {
+            plots.push(Plot { x, north: true });
+            plots.push(Plot { x, north: false });
+            x += PLOT_SPACING;
+        }
+    }
+    plots
+}
+
+/// Fill the blocks behind the houses. A row of grass is left free behind the houses, so the
+/// districts never block the way to a house.
+fn add_districts(
+    rng: &mut GameRng,
+    tiles: &mut TileMap,
+    main_street: u32,
+    cross_streets: &[CrossStreet],
+) {
+    let mut edges = vec![0];
+    for street in cross_streets {
+        edges.push(street.column);
+        edges.push(street.column + 2);
+    }
+    edges.push(tiles.width);
+    let rows = [
+        (0, main_street.saturating_sub(3), true),
+        (main_street + 5, tiles.height, false),
+    ];
+    for &(min_y, max_y, north) in &rows {
+        for columns in edges.chunks(2) {
+            let (min_x, max_x) = (columns[0] + 1, columns[1].saturating_sub(1));
+            if min_x >= max_x || min_y >= max_y {
+                continue;
+            }
+            let district = match rng.gen_range(0, 4) {
+                0 => District::Meadow,
+                1 => District::Park,
+                2 => District::Pond,
+                _ => District::Garden,
+            };
+            add_district(rng, tiles, district, (min_x, min_y, max_x, max_y), north);
+        }
+    }
+}
+
+/// Fill the block from `(min_x, min_y)` up to but not including `(max_x, max_y)`.
+fn add_district(
+    rng: &mut GameRng,
+    tiles: &mut TileMap,
+    district: District,
+    (min_x, min_y, max_x, max_y): (u32, u32, u32, u32),
+    north: bool,
+) {
+    match district {
+        District::Meadow => {}
+        District::Park => {
+            for y in min_y..max_y {
+                for x in min_x..max_x {
+                    if rng.gen_range(0, 5) == 0 {
+                        tiles.set(x, y, Terrain::Tree);
+                    }
+                }
+            }
+        }
+        District::Pond => {
+            if max_x - min_x >= 3 && max_y - min_y >= 3 {
+                for y in min_y + 1..max_y - 1 {
+                    for x in min_x + 1..max_x - 1 {
+                        tiles.set(x, y, Terrain::Water);
+                    }
+                }
+            }
+        }
+        District::Garden => {
+            if max_x - min_x >= 3 && max_y - min_y >= 3 {
+                for x in min_x..max_x {
+                    tiles.set(x, min_y, Terrain::Fence);
+                    tiles.set(x, max_y - 1, Terrain::Fence);
+                }
+                for y in min_y..max_y {
+                    tiles.set(min_x, y, Terrain::Fence);
+                    tiles.set(max_x - 1, y, Terrain::Fence);
+                }
+                // The gate faces the main street
+                let gate_y = if north { max_y - 1 } else { min_y };
+                tiles.set((min_x + max_x) / 2, gate_y, Terrain::Grass);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use world::rng_from_seed;
+
+    /// A config for a town whose size and number of houses depend on the seed.
+    fn config(seed: u64) -> Config {
+        let mut config = Config::default();
+        config.level_width = 12 + (seed % 30) as u32;
+        config.level_height = 8 + (seed % 15) as u32;
+        config.house_count = 1 + (seed % max_house_count(config.level_width) as u64) as u32;
+        config
+    }
+
+    /// Check the invariants every town should have.
+    fn check_town(level: &Level, seed: u64) {
+        assert!(
+            level.unreachable_houses().is_empty(),
+            "a house can't be reached with seed {}",
+            seed
+        );
+        let numbers: HashSet<u32> = level.houses.iter().map(|house| house.number).collect();
+        assert_eq!(numbers.len(), level.houses.len(), "seed {}", seed);
+        let side = |odd: bool| -> HashSet<u32> {
+            level
+                .houses
+                .iter()
+                .filter(|house| (house.number % 2 == 1) == odd)
+                .map(|house| house.position[1] as u32)
+                .collect()
+        };
+        let (north, south) = (side(true), side(false));
+        assert!(north.len() <= 1 && south.len() <= 1, "seed {}", seed);
+        for (odd_y, even_y) in north.iter().zip(south.iter()) {
+            assert!(odd_y < even_y, "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn towns_keep_their_invariants() {
+        for seed in 0..500 {
+            let config = config(seed);
+            let level = generate(&config, &mut rng_from_seed(seed), 4);
+            assert_eq!(level.houses.len(), config.house_count as usize);
+            check_town(&level, seed);
+        }
+    }
+
+    #[test]
+    fn failed_layouts_leave_only_streets() {
+        for seed in 0..100 {
+            let level = generate_with_attempts(&config(seed), &mut rng_from_seed(seed), 4, 0);
+            check_town(&level, seed);
+            let tiles = level.tile_map().unwrap();
+            for y in 0..tiles.height {
+                for x in 0..tiles.width {
+                    match tiles.get(x, y) {
+                        Terrain::Grass | Terrain::Road => {}
+                        terrain => panic!("{:?} at {}, {} with seed {}", terrain, x, y, seed),
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,441 @@
+use ggez::graphics::spritebatch::SpriteBatch;
+use ggez::graphics::{self, Color, DrawParam, Drawable, FilterMode, Point2, Rect, Text, Vector2};
+use ggez::{Context, GameResult};
+
+use atlas::Region;
+use images::Images;
+use layout::{Anchor, Layout};
+
+/// Create text in the default font, drawn without smoothing like the rest of the game.
+pub fn text(ctx: &mut Context, contents: &str) -> GameResult<Text> {
+    let mut text = Text::new(ctx, contents, &graphics::Font::default_font()?)?;
+    text.set_filter(FilterMode::Nearest);
+    Ok(text)
+}
+
+/// A single line of text.
+pub struct Label {
+    text: Text,
+    scale: f32,
+    color: Color,
+}
+
+impl Label {
+    pub fn new(ctx: &mut Context, contents: &str) -> GameResult<Label> {
+        Ok(Label {
+            text: text(ctx, contents)?,
+            scale: 1.0,
+            color: Color::new(1.0, 1.0, 1.0, 1.0),
+        })
+    }
+
+    pub fn with_scale(self, scale: f32) -> Label {
+        Label { scale, ..self }
+    }
+
+    pub fn set_text(&mut self, ctx: &mut Context, contents: &str) -> GameResult<()> {
+        self.text = text(ctx, contents)?;
+        Ok(())
+    }
+
+    pub fn set_color(&mut self, color: Color) {
+        self.color = color;
+    }
+
+    /// The size of the text, in pixels of the reference screen.
+    pub fn size(&self) -> Vector2 {
+        Vector2::new(self.text.width() as f32, self.text.height() as f32) * self.scale
+    }
+}
+
+/// Where the picture of a sprite comes from.
+pub enum SpriteSource {
+    Region(Region),
+    /// An animation clip, played with the time the interface is drawn at.
+    Clip(String),
+}
+
+/// A picture from the atlas, with an optional number over its centre like on letters.
+pub struct Sprite {
+    source: SpriteSource,
+    scale: Vector2,
+    /// The rotation around the centre in radians, clockwise.
+    rotation: f32,
+    color: Option<Color>,
+    number: Option<u32>,
+}
+
+impl Sprite {
+    pub fn new(source: SpriteSource, scale: f32) -> Sprite {
+        Sprite {
+            source,
+            scale: Vector2::new(scale, scale),
+            rotation: 0.0,
+            color: None,
+            number: None,
+        }
+    }
+
+    pub fn with_number(self, number: u32) -> Sprite {
+        Sprite {
+            number: Some(number),
+            ..self
+        }
+    }
+
+    pub fn set_color(&mut self, color: Option<Color>) {
+        self.color = color;
+    }
+
+    /// Turn the picture. The number on top stays upright, and the size the sprite takes up does
+    /// not change.
+    pub fn set_rotation(&mut self, rotation: f32) {
+        self.rotation = rotation;
+    }
+
+    /// The part of the atlas the sprite shows at `time`.
+    fn region(&self, images: &Images, time: f32) -> Region {
+        match self.source {
+            SpriteSource::Region(region) => region,
+            SpriteSource::Clip(ref clip) => images.animations.frame(clip, time).region,
+        }
+    }
+
+    fn size(&self, images: &Images) -> Vector2 {
+        let region = self.region(images, 0.0);
+        Vector2::new(
+            region.w as f32 * self.scale[0],
+            region.h as f32 * self.scale[1],
+        )
+    }
+
+    fn add(&self, target: &mut Target, centre: Point2, scale: f32) {
+        let param = DrawParam {
+            dest: centre,
+            offset: Point2::new(0.5, 0.5),
+            scale: Point2::origin() + self.scale * scale,
+            color: self.color,
+            ..Default::default()
+        };
+        let turned = DrawParam {
+            rotation: self.rotation,
+            ..param
+        };
+        match self.source {
+            SpriteSource::Region(region) => target.images.add(target.batch, region, turned),
+            SpriteSource::Clip(ref clip) => {
+                target
+                    .images
+                    .add_clip(target.batch, clip, target.time, turned)
+            }
+        }
+        if let Some(number) = self.number {
+            target.images.add_number(
+                target.batch,
+                number,
+                DrawParam {
+                    color: Some(Color::new(0.0, 0.0, 0.0, 1.0)),
+                    ..param
+                },
+            );
+        }
+    }
+}
+
+/// An image that is filled from the left by another one.
+pub struct ProgressBar {
+    background: Region,
+    fill: Region,
+    scale: Vector2,
+    /// How much of the bar is filled, from 0 to 1.
+    value: f32,
+}
+
+impl ProgressBar {
+    pub fn new(background: Region, fill: Region, scale: Vector2) -> ProgressBar {
+        ProgressBar {
+            background,
+            fill,
+            scale,
+            value: 0.0,
+        }
+    }
+
+    pub fn set_value(&mut self, value: f32) {
+        self.value = value.max(0.0).min(1.0);
+    }
+
+    fn size(&self) -> Vector2 {
+        Vector2::new(
+            self.background.w as f32 * self.scale[0],
+            self.background.h as f32 * self.scale[1],
+        )
+    }
+
+    fn add(&self, target: &mut Target, top_left: Point2, scale: f32) {
+        let param = DrawParam {
+            dest: top_left,
+            scale: Point2::origin() + self.scale * scale,
+            ..Default::default()
+        };
+        target.images.add(target.batch, self.background, param);
+        target.images.add(
+            target.batch,
+            self.fill,
+            DrawParam {
+                src: Rect::new(0.0, 0.0, self.value, 1.0),
+                ..param
+            },
+        );
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Horizontal,
+    Vertical,
+}
+
+impl Direction {
+    /// The index of the axis the children are placed along.
+    fn axis(self) -> usize {
+        match self {
+            Direction::Horizontal => 0,
+            Direction::Vertical => 1,
+        }
+    }
+}
+
+/// Places its children one after the other.
+pub struct StackPanel {
+    direction: Direction,
+    /// The space between two children. A negative gap makes them overlap.
+    gap: f32,
+    /// Where the children are placed across the direction of the stack, from 0 for the start
+    /// to 1 for the end.
+    align: f32,
+    children: Vec<Widget>,
+}
+
+impl StackPanel {
+    pub fn new(direction: Direction, gap: f32, align: f32) -> StackPanel {
+        StackPanel {
+            direction,
+            gap,
+            align,
+            children: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, child: Widget) {
+        self.children.push(child);
+    }
+
+    pub fn clear(&mut self) {
+        self.children.clear();
+    }
+
+    pub fn child_mut(&mut self, index: usize) -> &mut Widget {
+        &mut self.children[index]
+    }
+
+    fn size(&self, images: &Images) -> Vector2 {
+        let along = self.direction.axis();
+        let mut size = Vector2::new(0.0, 0.0);
+        for (i, child) in self.children.iter().enumerate() {
+            let child_size = child.size(images);
+            if i > 0 {
+                size[along] += self.gap;
+            }
+            size[along] += child_size[along];
+            size[1 - along] = size[1 - along].max(child_size[1 - along]);
+        }
+        size
+    }
+
+    fn add<'a, 'b>(&'a self, target: &mut Target<'b, 'a>, top_left: Point2, scale: f32) {
+        let along = self.direction.axis();
+        let size = self.size(target.images);
+        let mut position = top_left;
+        for child in &self.children {
+            let child_size = child.size(target.images);
+            let mut child_top_left = position;
+            let space = size[1 - along] - child_size[1 - along];
+            child_top_left[1 - along] += space * self.align * scale;
+            child.add(target, child_top_left, scale);
+            position[along] += (child_size[along] + self.gap) * scale;
+        }
+    }
+}
+
+/// Something the interface shows.
+pub enum Widget {
+    Label(Label),
+    Sprite(Sprite),
+    ProgressBar(ProgressBar),
+    Stack(StackPanel),
+}
+
+impl Widget {
+    /// The size of the widget, in pixels of the reference screen.
+    fn size(&self, images: &Images) -> Vector2 {
+        match *self {
+            Widget::Label(ref label) => label.size(),
+            Widget::Sprite(ref sprite) => sprite.size(images),
+            Widget::ProgressBar(ref bar) => bar.size(),
+            Widget::Stack(ref stack) => stack.size(images),
+        }
+    }
+
+    /// Add the widget to the target with its top left at `top_left` on the screen.
+    fn add<'a, 'b>(&'a self, target: &mut Target<'b, 'a>, top_left: Point2, scale: f32) {
+        match *self {
+            Widget::Label(ref label) => target.labels.push((label, top_left, scale)),
+            Widget::Sprite(ref sprite) => {
+                let centre = top_left + sprite.size(target.images) * scale / 2.0;
+                sprite.add(target, centre, scale)
+            }
+            Widget::ProgressBar(ref bar) => bar.add(target, top_left, scale),
+            Widget::Stack(ref stack) => stack.add(target, top_left, scale),
+        }
+    }
+
+    // The widgets are looked up by the code that made them, so asking for the wrong kind is a
+    // mistake in that code.
+
+    pub fn label_mut(&mut self) -> &mut Label {
+        match *self {
+            Widget::Label(ref mut label) => label,
+            _ => panic!("The widget is not a label"),
+        }
+    }
+
+    pub fn sprite_mut(&mut self) -> &mut Sprite {
+        match *self {
+            Widget::Sprite(ref mut sprite) => sprite,
+            _ => panic!("The widget is not a sprite"),
+        }
+    }
+
+    pub fn progress_bar_mut(&mut self) -> &mut ProgressBar {
+        match *self {
+            Widget::ProgressBar(ref mut bar) => bar,
+            _ => panic!("The widget is not a progress bar"),
+        }
+    }
+
+    pub fn stack_mut(&mut self) -> &mut StackPanel {
+        match *self {
+            Widget::Stack(ref mut stack) => stack,
+            _ => panic!("The widget is not a stack panel"),
+        }
+    }
+}
+
+/// What the widgets are drawn with: sprites are gathered in a batch, and the labels are drawn
+/// over them afterwards.
+struct Target<'b, 'a: 'b> {
+    images: &'a Images,
+    batch: &'b mut SpriteBatch,
+    labels: Vec<(&'a Label, Point2, f32)>,
+    time: f32,
+}
+
+/// Refers to a widget that was added to a `Ui`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WidgetId(usize);
+
+/// A widget placed relative to an anchor of the screen.
+struct Element {
+    anchor: Anchor,
+    /// How far the widget is from the anchor, in pixels of the reference screen.
+    offset: Vector2,
+    /// Which point of the widget is placed there, as a fraction of its size.
+    align: Vector2,
+    visible: bool,
+    widget: Widget,
+}
+
+/// A set of widgets that stay around between frames and are changed when what they show
+/// changes, rather than being laid out again by hand every frame.
+pub struct Ui {
+    elements: Vec<Element>,
+    /// The batch the sprites are drawn with, made when they are first drawn.
+    batch: Option<SpriteBatch>,
+}
+
+impl Ui {
+    pub fn new() -> Ui {
+        Ui {
+            elements: Vec::new(),
+            batch: None,
+        }
+    }
+
+    /// Place a widget `offset` reference pixels away from the anchor, with the point at
+    /// `align` of its size there. An `align` of (0.5, 0.5) centres it.
+    pub fn add(
+        &mut self,
+        anchor: Anchor,
+        offset: Vector2,
+        align: Vector2,
+        widget: Widget,
+    ) -> WidgetId {
+        self.elements.push(Element {
+            anchor,
+            offset,
+            align,
+            visible: true,
+            widget,
+        });
+        WidgetId(self.elements.len() - 1)
+    }
+
+    pub fn get_mut(&mut self, id: WidgetId) -> &mut Widget {
+        &mut self.elements[id.0].widget
+    }
+
+    pub fn set_offset(&mut self, id: WidgetId, offset: Vector2) {
+        self.elements[id.0].offset = offset;
+    }
+
+    pub fn set_visible(&mut self, id: WidgetId, visible: bool) {
+        self.elements[id.0].visible = visible;
+    }
+
+    /// Draw every visible widget, with clips showing the frame at `time`.
+    pub fn draw(&mut self, ctx: &mut Context, images: &Images, time: f32) -> GameResult<()> {
+        let layout = Layout::new(ctx);
+        let elements = &self.elements;
+        let batch = self.batch.get_or_insert_with(|| images.batch());
+        batch.clear();
+        let labels = {
+            let mut target = Target {
+                images,
+                batch,
+                labels: Vec::new(),
+                time,
+            };
+            for element in elements.iter().filter(|element| element.visible) {
+                let size = element.widget.size(images) * layout.scale();
+                let top_left = layout.point(element.anchor, element.offset)
+                    - Vector2::new(size[0] * element.align[0], size[1] * element.align[1]);
+                element.widget.add(&mut target, top_left, layout.scale());
+            }
+            target.labels
+        };
+        graphics::draw_ex(ctx, batch, DrawParam::default())?;
+        for (label, top_left, scale) in labels {
+            label.text.draw_ex(
+                ctx,
+                DrawParam {
+                    dest: top_left,
+                    scale: Point2::new(label.scale * scale, label.scale * scale),
+                    color: Some(label.color),
+                    ..Default::default()
+                },
+            )?;
+        }
+        Ok(())
+    }
+}
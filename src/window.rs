@@ -0,0 +1,96 @@
+use ggez::graphics;
+use ggez::{Context, GameResult};
+
+use std::io::{Read, Write};
+
+use toml;
+
+use layout;
+
+/// Where the window settings are stored, in the user config directory.
+const PATH: &str = "/window.toml";
+
+/// The smallest window size that is remembered, in pixels.
+pub const MIN_WIDTH: u32 = 320;
+pub const MIN_HEIGHT: u32 = 240;
+
+/// The size of the window and whether it is fullscreen, kept between runs.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WindowSettings {
+    /// The size of the window when it isn't fullscreen.
+    pub width: u32,
+    pub height: u32,
+    pub fullscreen: bool,
+}
+
+impl Default for WindowSettings {
+    fn default() -> WindowSettings {
+        WindowSettings {
+            width: 800,
+            height: 600,
+            fullscreen: false,
+        }
+    }
+}
+
+impl WindowSettings {
+    /// Load the settings from the user config directory, or use the defaults if they can't be
+    /// read.
+    pub fn load(ctx: &mut Context) -> WindowSettings {
+        if !ctx.filesystem.exists(PATH) {
+            return WindowSettings::default();
+        }
+        let mut source = String::new();
+        let read = ctx
+            .filesystem
+            .open(PATH)
+            .and_then(|mut file| Ok(file.read_to_string(&mut source)?));
+        if let Err(e) = read {
+            println!("Could not read the window settings: {}", e);
+            return WindowSettings::default();
+        }
+        match toml::from_str::<WindowSettings>(&source) {
+            Ok(settings) => WindowSettings {
+                width: settings.width.max(MIN_WIDTH),
+                height: settings.height.max(MIN_HEIGHT),
+                ..settings
+            },
+            Err(e) => {
+                println!("Ignoring the window settings in {}: {}", PATH, e);
+                WindowSettings::default()
+            }
+        }
+    }
+
+    pub fn save(&self, ctx: &mut Context) -> GameResult<()> {
+        // Serializing numbers and a flag can't fail
+        let source = toml::to_string(self).expect("Could not write the window settings");
+        let mut writer = ctx.filesystem.create(PATH)?;
+        writeln!(writer, "# The window, as it was when the game closed.")?;
+        writer.write_all(source.as_bytes())?;
+        Ok(())
+    }
+
+    /// Resize the window and go to or leave fullscreen to match the settings.
+    pub fn apply(&self, ctx: &mut Context) -> GameResult<()> {
+        graphics::set_resolution(ctx, self.width, self.height)?;
+        graphics::set_fullscreen(ctx, self.fullscreen)?;
+        layout::fit_screen(ctx)
+    }
+
+    pub fn toggle_fullscreen(&mut self, ctx: &mut Context) -> GameResult<()> {
+        self.fullscreen = !self.fullscreen;
+        graphics::set_fullscreen(ctx, self.fullscreen)?;
+        layout::fit_screen(ctx)
+    }
+
+    /// Remember the new size of the window. The size of a fullscreen window isn't kept, so
+    /// leaving fullscreen goes back to the earlier size.
+    pub fn resized(&mut self, width: u32, height: u32) {
+        if !self.fullscreen {
+            self.width = width.max(MIN_WIDTH);
+            self.height = height.max(MIN_HEIGHT);
+        }
+    }
+}
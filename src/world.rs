@@ -0,0 +1,827 @@
+use ggez::graphics::{Point2, Vector2};
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+
+use rand::prng::XorShiftRng;
+use rand::{thread_rng, Rng, SeedableRng};
+
+use config::Config;
+use level::{HouseSpec, Level, Obstacle};
+use scoring::{Delivery, Scoring};
+use tilemap::{Reachability, TileMap};
+
+/// The length of a single logic tick. The game always advances in steps of this size, so it
+/// plays the same regardless of the frame rate.
+pub const TICK_TIME: f32 = 1.0 / 60.0;
+
+/// Half the width of the part of a house the player can't walk through, in tiles.
+pub const HOUSE_HALF_SIZE: f32 = 1.0;
+/// Half the width of the postman when colliding, in tiles.
+pub const PLAYER_HALF_SIZE: f32 = 0.3;
+
+/// The gap left between the player and whatever they bump into, so rounding errors don't leave
+/// them stuck inside it.
+const COLLISION_MARGIN: f32 = 0.001;
+
+/// How close, in tiles, the player has to be to a house to deliver to it. This is a bit more
+/// than the distance at which the player bumps into it.
+pub const HOUSE_REACH: f32 = HOUSE_HALF_SIZE + PLAYER_HALF_SIZE + 0.2;
+/// How close, in tiles, the player has to be to a letter to pick it up.
+const LETTER_REACH: f32 = 0.9;
+
+/// The random positions tried for a new letter before it is put on one of the free tiles
+/// instead.
+const LETTER_PLACEMENT_ATTEMPTS: u32 = 100;
+
+/// The seconds a dropped letter can't be picked up again.
+const DROP_COOLDOWN: f64 = 2.0;
+
+/// How far the movement input has to be pushed before the player starts walking.
+const MOVEMENT_THRESHOLD: f32 = 0.1;
+
+/// An axis aligned box in tiles, used for collisions.
+#[derive(Clone, Copy, Debug)]
+pub struct Bounds {
+    pub min: Point2,
+    pub max: Point2,
+}
+
+impl Bounds {
+    pub fn around(centre: Point2, half_size: f32) -> Bounds {
+        let half_size = Vector2::new(half_size, half_size);
+        Bounds {
+            min: centre - half_size,
+            max: centre + half_size,
+        }
+    }
+
+    pub fn overlaps(&self, other: &Bounds) -> bool {
+        self.min[0] < other.max[0]
+            && self.max[0] > other.min[0]
+            && self.min[1] < other.max[1]
+            && self.max[1] > other.min[1]
+    }
+}
+
+impl Obstacle {
+    pub fn bounds(&self) -> Bounds {
+        Bounds {
+            min: Point2::new(self.position[0], self.position[1]),
+            max: Point2::new(
+                self.position[0] + self.size[0],
+                self.position[1] + self.size[1],
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct House {
+    pub position: Point2,
+    pub number: u32,
+    pub resource_type: usize,
+}
+
+impl House {
+    fn new(rng: &mut GameRng, spec: &HouseSpec, image_count: usize) -> House {
+        House {
+            position: Point2::new(spec.position[0], spec.position[1]),
+            number: spec.number,
+            resource_type: spec
+                .sprite
+                .unwrap_or_else(|| rng.gen_range(0, image_count)),
+        }
+    }
+
+    pub fn bounds(&self) -> Bounds {
+        Bounds::around(self.position, HOUSE_HALF_SIZE)
+    }
+
+    fn player_intersection(&self, player: Point2) -> bool {
+        let dist = self.position - player;
+        dist[0].abs() < HOUSE_REACH && dist[1].abs() < HOUSE_REACH
+    }
+}
+
+/// The kinds of letters, which follow different rules. How often each spawns and what it is
+/// worth is set in the config.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum LetterKind {
+    Normal,
+    /// Worth more when delivered quickly.
+    Express,
+    /// Takes two places in the bag.
+    Parcel,
+    /// Only handed over after standing at the house for a while.
+    Registered,
+    /// Breaks when it falls out of a full bag.
+    Fragile,
+}
+
+impl LetterKind {
+    pub const ALL: [LetterKind; 5] = [
+        LetterKind::Normal,
+        LetterKind::Express,
+        LetterKind::Parcel,
+        LetterKind::Registered,
+        LetterKind::Fragile,
+    ];
+
+    /// The name used in the config.
+    pub fn name(self) -> &'static str {
+        match self {
+            LetterKind::Normal => "normal",
+            LetterKind::Express => "express",
+            LetterKind::Parcel => "parcel",
+            LetterKind::Registered => "registered",
+            LetterKind::Fragile => "fragile",
+        }
+    }
+
+    /// The number of places the letter takes up in the bag.
+    pub fn size(self) -> usize {
+        match self {
+            LetterKind::Parcel => 2,
+            _ => 1,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Letter {
+    pub number: u32,
+    pub kind: LetterKind,
+    pub position: Point2,
+    /// The time the letter appeared on the ground.
+    pub spawn_time: f64,
+    /// The time the letter should be delivered by.
+    pub due_time: f64,
+    pub dropped_time: f64,
+}
+
+impl Letter {
+    fn new(position: Point2, number: u32, kind: LetterKind, time: f64, config: &Config) -> Letter {
+        Letter {
+            number,
+            kind,
+            position,
+            spawn_time: time,
+            due_time: time + config.due_time as f64,
+            dropped_time: 0.0,
+        }
+    }
+
+    /// The seconds since the letter appeared.
+    pub fn age(&self, time: f64) -> f64 {
+        time - self.spawn_time
+    }
+
+    /// How far the letter is towards being due, from 0 when it appears to 1 when it is due.
+    pub fn lateness(&self, time: f64) -> f32 {
+        (self.age(time) / (self.due_time - self.spawn_time)).max(0.0) as f32
+    }
+
+    pub fn overdue(&self, time: f64) -> bool {
+        time > self.due_time
+    }
+
+    /// The number of places the letter takes on the ground. Letters that have been overdue for
+    /// too long take two.
+    pub fn ground_size(&self, config: &Config, time: f64) -> u32 {
+        if time > self.due_time + config.overdue_time as f64 {
+            2
+        } else {
+            1
+        }
+    }
+
+    /// What delivering the letter at `time` is worth by its kind. Express letters are only
+    /// worth their bonus until the deadline.
+    pub fn score(&self, config: &Config, time: f64) -> u32 {
+        let late = self.age(time) > config.express_time as f64;
+        if self.kind == LetterKind::Express && late {
+            config.letters.get(LetterKind::Normal).score
+        } else {
+            config.letters.get(self.kind).score
+        }
+    }
+
+    /// The bonus for delivering the letter at `time`. It starts at the full bonus and shrinks
+    /// as the letter gets closer to being due, and is gone once it is overdue.
+    pub fn on_time_bonus(&self, config: &Config, time: f64) -> u32 {
+        if self.overdue(time) {
+            0
+        } else {
+            (config.on_time_bonus as f32 * (1.0 - self.lateness(time))).ceil() as u32
+        }
+    }
+
+    /// Whether the letter was dropped recently and can't be picked up yet.
+    pub fn on_cooldown(&self, time: f64) -> bool {
+        self.dropped_time > time
+    }
+
+    fn player_intersection(&self, player: Point2, time: f64) -> bool {
+        let dist = self.position - player;
+        dist[0].abs() < LETTER_REACH && dist[1].abs() < LETTER_REACH && !self.on_cooldown(time)
+    }
+}
+
+/// The random number generator used for everything in the game, so a run can be reproduced
+/// from its seed.
+pub type GameRng = XorShiftRng;
+
+/// Pick a random seed for a new game.
+pub fn random_seed() -> u64 {
+    thread_rng().gen()
+}
+
+pub fn rng_from_seed(seed: u64) -> GameRng {
+    let mut bytes = [0; 16];
+    for i in 0..8 {
+        bytes[i] = (seed >> (8 * i)) as u8;
+        // Xorshift can't be seeded with all zeroes
+        bytes[i + 8] = !bytes[i];
+    }
+    GameRng::from_seed(bytes)
+}
+
+/// Something the player does with the letters in the bag.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BagAction {
+    /// Move the front letter to the back of the bag.
+    Rotate,
+    /// Swap the front two letters.
+    Swap,
+    /// Put the front letter down on the ground.
+    Drop,
+}
+
+impl BagAction {
+    pub const ALL: [BagAction; 3] = [BagAction::Rotate, BagAction::Swap, BagAction::Drop];
+
+    /// The name used in replays.
+    pub fn name(self) -> &'static str {
+        match self {
+            BagAction::Rotate => "rotate",
+            BagAction::Swap => "swap",
+            BagAction::Drop => "drop",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<BagAction> {
+        BagAction::ALL
+            .iter()
+            .cloned()
+            .find(|action| action.name() == name)
+    }
+}
+
+/// What the player asks for during an update.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Input {
+    /// The direction to walk in. Its length is the fraction of the full speed to walk at, where
+    /// anything longer than 1 counts as 1.
+    pub direction: Vector2,
+    /// What to do with the bag at the start of the update, if anything.
+    pub bag: Option<BagAction>,
+}
+
+impl Default for Input {
+    fn default() -> Input {
+        Input {
+            direction: Vector2::new(0.0, 0.0),
+            bag: None,
+        }
+    }
+}
+
+/// What happened during a single `World::update`, so the front end can play sounds and
+/// animations.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Events {
+    pub picked_up: bool,
+    /// A letter fell out of the bag because it was full.
+    pub dropped: bool,
+    /// The player put a letter down on purpose.
+    pub put_down: bool,
+    /// A fragile letter fell out of the bag and broke.
+    pub broke: bool,
+    pub delivery: Option<Delivery>,
+}
+
+/// The game rules, without any rendering or dependency on a `Context`.
+///
+/// All positions are in tiles, with the player position being the centre of the postman.
+pub struct World {
+    pub config: Config,
+    pub seed: u64,
+    rng: GameRng,
+
+    /// The size of the level in tiles.
+    pub width: u32,
+    pub height: u32,
+    pub obstacles: Vec<Obstacle>,
+    pub tiles: TileMap,
+    /// The tiles the player can walk to from the spawn point.
+    reachable: Reachability,
+    /// The centres of the tiles a letter can appear on.
+    letter_tiles: Vec<Point2>,
+
+    pub player: Point2,
+    /// The player's velocity in tiles per second.
+    pub player_velocity: Vector2,
+    /// The direction the player faces, as an angle in radians clockwise from up.
+    pub player_heading: f32,
+    pub player_running: bool,
+
+    pub houses: Vec<House>,
+    pub letters: Vec<Letter>,
+    pub holding_letters: VecDeque<Letter>,
+    /// How long the player has been standing at the house the registered letter at the front of
+    /// the bag goes to.
+    pub handover_time: f32,
+
+    pub time: f64,
+    pub time_since_last_letter: f32,
+    pub letter_spawn_time: f32,
+
+    pub scoring: Scoring,
+    pub game_over: bool,
+}
+
+impl World {
+    /// Start a game on the given level, or on a randomly generated one if there is none.
+    pub fn new(config: Config, level: Option<&Level>, resource_count: usize, seed: u64) -> World {
+        let mut rng = rng_from_seed(seed);
+        let level = match level {
+            Some(level) => level.clone(),
+            None => Level::generate(&config, &mut rng, resource_count),
+        };
+        let houses = level
+            .houses
+            .iter()
+            .map(|spec| House::new(&mut rng, spec, resource_count))
+            .collect();
+        let tiles = level.tile_map().expect("Invalid tiles in level");
+        let spawn = Point2::new(level.spawn[0], level.spawn[1]);
+        let reachable = tiles.reachable_from(spawn, &level.solids());
+        let letter_tiles = letter_tiles(&tiles, &reachable, &level.solids());
+        let letter_spawn_time = config.spawn_time;
+        World {
+            config,
+            seed,
+            rng,
+
+            width: level.width,
+            height: level.height,
+            obstacles: level.obstacles,
+            tiles,
+            reachable,
+            letter_tiles,
+
+            player: spawn,
+            player_velocity: Vector2::new(0.0, 0.0),
+            player_heading: PI,
+            player_running: false,
+
+            houses,
+            letters: Vec::new(),
+            holding_letters: VecDeque::new(),
+            handover_time: 0.0,
+
+            time: 0.0,
+            time_since_last_letter: 0.0,
+            letter_spawn_time,
+
+            scoring: Scoring::new(),
+            game_over: false,
+        }
+    }
+
+    pub fn score(&self) -> u32 {
+        self.scoring.score()
+    }
+
+    /// The number of places on the ground taken up by letters.
+    pub fn ground_load(&self) -> u32 {
+        self.letters
+            .iter()
+            .map(|letter| letter.ground_size(&self.config, self.time))
+            .sum()
+    }
+
+    /// The fraction of the ground that is filled with letters, including the letter that is
+    /// about to spawn.
+    pub fn ground_fill(&self) -> f32 {
+        ((self.ground_load() as f32 + self.time_since_last_letter / self.letter_spawn_time)
+            / self.config.max_letters_on_ground as f32)
+            .min(1.0)
+    }
+
+    /// The letter that has been waiting the longest, on the ground or in the bag.
+    pub fn oldest_letter(&self) -> Option<&Letter> {
+        self.letters
+            .iter()
+            .chain(&self.holding_letters)
+            .min_by(|a, b| a.spawn_time.partial_cmp(&b.spawn_time).unwrap())
+    }
+
+    /// The house the letter at the front of the bag goes to.
+    pub fn next_house(&self) -> Option<&House> {
+        self.holding_letters.front().and_then(|letter| {
+            self.houses
+                .iter()
+                .find(|house| house.number == letter.number)
+        })
+    }
+
+    /// The number of places in the bag that are taken up.
+    pub fn bag_load(&self) -> usize {
+        self.holding_letters
+            .iter()
+            .map(|letter| letter.kind.size())
+            .sum()
+    }
+
+    /// The speed the player walks at when the input is pushed all the way. Every place taken up
+    /// in the bag slows the player down, as does rough terrain.
+    pub fn top_speed(&self) -> f32 {
+        let slowdown = self.config.letter_slowdown * self.bag_load() as f32;
+        self.config.player_speed
+            * (1.0 - slowdown).max(0.0)
+            * self.tiles.terrain_at(self.player).speed_modifier()
+    }
+
+    /// Change the velocity of the player towards the input. Pushing the input accelerates
+    /// towards the matching fraction of the top speed, letting go of it slows down by friction.
+    fn accelerate_player(&mut self, delta: f32, input: &Input) {
+        let mut direction = input.direction;
+        if direction.norm() > 1.0 {
+            direction = direction.normalize();
+        }
+        if direction.norm() > MOVEMENT_THRESHOLD {
+            self.player_heading = direction[0].atan2(-direction[1]);
+            let change = direction * self.top_speed() - self.player_velocity;
+            let max_change = self.config.player_acceleration * delta;
+            if change.norm() > max_change {
+                self.player_velocity += change.normalize() * max_change;
+            } else {
+                self.player_velocity += change;
+            }
+        } else {
+            let speed = self.player_velocity.norm();
+            let slowed = (speed - self.config.player_friction * delta).max(0.0);
+            if slowed > 0.0 {
+                self.player_velocity *= slowed / speed;
+            } else {
+                self.player_velocity = Vector2::new(0.0, 0.0);
+            }
+        }
+        self.player_running = self.player_velocity.norm() > 0.0;
+    }
+
+    /// Everything the player can't walk through.
+    pub fn solids(&self) -> Vec<Bounds> {
+        self.houses
+            .iter()
+            .map(House::bounds)
+            .chain(self.obstacles.iter().map(Obstacle::bounds))
+            .collect()
+    }
+
+    /// Move the player, stopping at the level edges and at anything solid. Both axes are moved
+    /// separately, so moving diagonally into a wall slides along it. Bumping into something
+    /// stops the velocity along that axis.
+    fn move_player(&mut self, movement: Vector2) {
+        let solids = self.solids();
+        let max = [self.width as f32 - 1.0, self.height as f32 - 1.0];
+        for axis in 0..2 {
+            let target = self.player[axis] + movement[axis];
+            self.player[axis] = target.max(0.0).min(max[axis]);
+            if self.player[axis] != target {
+                self.player_velocity[axis] = 0.0;
+            }
+            let player_bounds = Bounds::around(self.player, PLAYER_HALF_SIZE);
+            let blocking_tiles = self.tiles.blocking_tiles(&player_bounds);
+            for solid in solids.iter().chain(&blocking_tiles) {
+                if Bounds::around(self.player, PLAYER_HALF_SIZE).overlaps(solid) {
+                    // Push the player back out on the side they came from
+                    if movement[axis] > 0.0 {
+                        self.player[axis] = solid.min[axis] - PLAYER_HALF_SIZE - COLLISION_MARGIN;
+                    } else if movement[axis] < 0.0 {
+                        self.player[axis] = solid.max[axis] + PLAYER_HALF_SIZE + COLLISION_MARGIN;
+                    }
+                    self.player_velocity[axis] = 0.0;
+                }
+            }
+        }
+    }
+
+    /// A random position for a new letter, where the player can reach it. Letters appear away
+    /// from the edges, unless there is no room there.
+    fn random_free_position(&mut self) -> Point2 {
+        let solids = self.solids();
+        for _ in 0..LETTER_PLACEMENT_ATTEMPTS {
+            let position = Point2::new(
+                self.rng.gen_range(2.0, self.width as f32 - 2.0),
+                self.rng.gen_range(2.0, self.height as f32 - 2.0),
+            );
+            if letter_fits(&self.tiles, &self.reachable, &solids, position) {
+                return position;
+            }
+        }
+        // Level files without any letter tiles are rejected, and generated levels are mostly
+        // open, so this only falls back to the player in levels made some other way
+        if self.letter_tiles.is_empty() {
+            return self.player;
+        }
+        let index = self.rng.gen_range(0, self.letter_tiles.len());
+        self.letter_tiles[index]
+    }
+
+    /// Pick the kind of a new letter, as often as the weights in the config say.
+    fn random_letter_kind(&mut self) -> LetterKind {
+        let mut roll = self.rng.gen_range(0, self.config.letters.total_weight());
+        for &kind in LetterKind::ALL.iter() {
+            let weight = self.config.letters.get(kind).weight;
+            if roll < weight {
+                return kind;
+            }
+            roll -= weight;
+        }
+        unreachable!("The roll is below the total weight")
+    }
+
+    /// Put a letter that was picked up in the bag: at the front, or in order of the house
+    /// numbers when the bag is sorted.
+    fn add_to_bag(&mut self, letter: Letter) {
+        if self.config.sorted_bag {
+            let index = self
+                .holding_letters
+                .iter()
+                .position(|held| held.number > letter.number)
+                .unwrap_or_else(|| self.holding_letters.len());
+            self.holding_letters.insert(index, letter);
+        } else {
+            self.holding_letters.push_front(letter);
+        }
+    }
+
+    /// Reorder the bag or put down its front letter. A sorted bag can't be reordered.
+    fn use_bag(&mut self, action: BagAction, events: &mut Events) {
+        match action {
+            BagAction::Rotate if !self.config.sorted_bag => {
+                if let Some(letter) = self.holding_letters.pop_front() {
+                    self.holding_letters.push_back(letter);
+                }
+            }
+            BagAction::Swap if !self.config.sorted_bag => {
+                if self.holding_letters.len() >= 2 {
+                    self.holding_letters.swap(0, 1);
+                }
+            }
+            BagAction::Drop => {
+                if let Some(mut letter) = self.holding_letters.pop_front() {
+                    letter.dropped_time = self.time + DROP_COOLDOWN;
+                    letter.position = self.player;
+                    self.letters.push(letter);
+                    events.put_down = true;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Advance the simulation by `delta` seconds with the given input held down.
+    pub fn update(&mut self, delta: f32, input: &Input) -> Events {
+        let mut events = Events::default();
+        if self.game_over {
+            return events;
+        }
+
+        self.time += delta as f64;
+        self.accelerate_player(delta, input);
+        if self.player_running {
+            let movement = self.player_velocity * delta;
+            self.move_player(movement);
+        }
+
+        if let Some(action) = input.bag {
+            self.use_bag(action, &mut events);
+        }
+
+        let d = delta * self.config.player_speed;
+
+        self.time_since_last_letter += d;
+        while self.time_since_last_letter > self.letter_spawn_time {
+            self.time_since_last_letter -= self.letter_spawn_time;
+            self.letter_spawn_time *= self.config.spawn_acceleration;
+            let position = self.random_free_position();
+            let number = self.houses[self.rng.gen_range(0, self.houses.len())].number;
+            let kind = self.random_letter_kind();
+            let letter = Letter::new(position, number, kind, self.time, &self.config);
+            self.letters.push(letter);
+        }
+
+        // Pick up letters
+        let mut not_picked_up = Vec::new();
+        while let Some(letter) = self.letters.pop() {
+            if letter.player_intersection(self.player, self.time) {
+                self.add_to_bag(letter);
+                events.picked_up = true;
+                while self.bag_load() > self.config.max_holding {
+                    let mut dropped_letter = self.holding_letters.pop_back().unwrap();
+                    if dropped_letter.kind == LetterKind::Fragile {
+                        self.scoring.penalise(self.config.fragile_penalty);
+                        events.broke = true;
+                    } else {
+                        dropped_letter.dropped_time = self.time + DROP_COOLDOWN;
+                        dropped_letter.position = self.player;
+                        not_picked_up.push(dropped_letter);
+                        events.dropped = true;
+                    }
+                }
+            } else {
+                not_picked_up.push(letter);
+            }
+        }
+        self.letters = not_picked_up;
+
+        // Drop off letters
+        let mut handing_over = false;
+        for house in &self.houses {
+            if house.player_intersection(self.player) {
+                let mut delivered = Vec::new();
+                while self.holding_letters.len() > 0
+                    && self.holding_letters[0].number == house.number
+                {
+                    if self.holding_letters[0].kind == LetterKind::Registered {
+                        handing_over = true;
+                        if self.handover_time < self.config.registered_time {
+                            break;
+                        }
+                        self.handover_time = 0.0;
+                    }
+                    delivered.push(self.holding_letters.pop_front().unwrap());
+                }
+                if !delivered.is_empty() {
+                    let delivery = self.scoring.deliver(&self.config, &delivered, self.time);
+                    events.delivery = Some(delivery);
+                }
+            }
+        }
+        self.handover_time = if handing_over {
+            self.handover_time + delta
+        } else {
+            0.0
+        };
+
+        if self.ground_load() >= self.config.max_letters_on_ground {
+            self.game_over = true;
+        }
+
+        events
+    }
+}
+
+/// The centres of the tiles a letter can appear on: tiles the player can walk to, where no
+/// house or obstacle is in the way of picking it up.
+pub fn letter_tiles(tiles: &TileMap, reachable: &Reachability, solids: &[Bounds]) -> Vec<Point2> {
+    let mut free = Vec::new();
+    for y in 0..tiles.height {
+        for x in 0..tiles.width {
+            let position = Point2::new(x as f32 + 0.5, y as f32 + 0.5);
+            if letter_fits(tiles, reachable, solids, position) {
+                free.push(position);
+            }
+        }
+    }
+    free
+}
+
+/// Whether a letter can appear at `position` and be picked up there.
+fn letter_fits(
+    tiles: &TileMap,
+    reachable: &Reachability,
+    solids: &[Bounds],
+    position: Point2,
+) -> bool {
+    let letter = Bounds::around(position, LETTER_REACH);
+    let standing = Bounds::around(position, PLAYER_HALF_SIZE);
+    solids.iter().all(|solid| !letter.overlaps(solid))
+        && tiles.blocking_tiles(&standing).is_empty()
+        && reachable.contains(position)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use replay::{Playback, Replay};
+
+    /// The number of house images the games are played with.
+    const HOUSE_IMAGES: usize = 4;
+    /// An hour of ticks, which every game should end well within.
+    const MAX_TICKS: u64 = 60 * 60 * 60;
+
+    /// Play a game on a generated level until it is over, and return the number of ticks it
+    /// lasted and the final score.
+    fn play<F: FnMut(&World, u64) -> Input>(seed: u64, mut input: F) -> (u64, u32) {
+        let mut world = World::new(Config::default(), None, HOUSE_IMAGES, seed);
+        let mut ticks = 0;
+        while !world.game_over {
+            assert!(ticks < MAX_TICKS, "the game with seed {} didn't end", seed);
+            let input = input(&world, ticks);
+            world.update(TICK_TIME, &input);
+            ticks += 1;
+        }
+        (ticks, world.score())
+    }
+
+    /// Walk to the house of the letter at the front of the bag, or to a letter on the ground
+    /// when the bag is empty, and rotate the bag every now and then.
+    fn walk_to_letters(world: &World, tick: u64) -> Input {
+        let target = match world.next_house() {
+            Some(house) => house.position,
+            None => match world.letters.iter().find(|l| !l.on_cooldown(world.time)) {
+                Some(letter) => letter.position,
+                None => world.player,
+            },
+        };
+        let direction = target - world.player;
+        Input {
+            direction: if direction.norm() > 1.0 {
+                direction.normalize()
+            } else {
+                direction
+            },
+            bag: if tick % 600 == 599 {
+                Some(BagAction::Rotate)
+            } else {
+                None
+            },
+        }
+    }
+
+    #[test]
+    fn idle_games_end() {
+        for seed in 0..1000 {
+            let (_, score) = play(seed, |_, _| Input::default());
+            assert_eq!(score, 0);
+        }
+    }
+
+    #[test]
+    fn letters_appear_when_the_middle_is_blocked() {
+        let level = Level::parse(
+            r#"
+            width = 12
+            height = 8
+            spawn = [0.5, 0.5]
+            houses = [{ position = [11.0, 4.0], number = 1 }]
+            obstacles = [{ position = [2.0, 2.0], size = [8.0, 4.0] }]
+            "#,
+            HOUSE_IMAGES,
+        )
+        .unwrap();
+        let mut world = World::new(Config::default(), Some(&level), HOUSE_IMAGES, 0);
+        while !world.game_over {
+            world.update(TICK_TIME, &Input::default());
+        }
+        let obstacle = level.obstacles[0].bounds();
+        assert!(!world.letters.is_empty());
+        for letter in &world.letters {
+            assert!(!Bounds::around(letter.position, LETTER_REACH).overlaps(&obstacle));
+        }
+    }
+
+    #[test]
+    fn same_seed_gives_same_result() {
+        let mut total_score = 0;
+        for seed in 0..20 {
+            let first = play(seed, walk_to_letters);
+            assert_eq!(first, play(seed, walk_to_letters));
+            total_score += first.1;
+        }
+        assert!(total_score > 0, "no letter was ever delivered");
+    }
+
+    #[test]
+    fn replay_plays_back_the_same() {
+        for seed in 0..20 {
+            let mut replay = Replay::new(seed, None, &Config::default());
+            let result = play(seed, |world, tick| {
+                let input = walk_to_letters(world, tick);
+                replay.record_tick(input);
+                input
+            });
+            replay.result = Some(result);
+
+            let mut file = Vec::new();
+            replay.write(&mut file).unwrap();
+            let mut playback = Playback::new(Replay::read(&file[..]).unwrap());
+            let played = play(seed, |_, tick| playback.input_for_tick(tick));
+            assert_eq!(Some(played), playback.replay().result);
+        }
+    }
+}